@@ -0,0 +1,856 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+
+const PROGRAM_START_ADDRESS: u16 = 0x200;
+/// Expansions allowed before a macro is assumed to be calling itself forever.
+const MAX_MACRO_EXPANSIONS: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+/// An assembled ROM image, starting at 0x200.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub bytes: Vec<u8>,
+    pub labels: BTreeMap<String, u16>,
+    /// The source line of each instruction, by address. Instructions from a macro
+    /// get the line in the macro's body.
+    pub lines: BTreeMap<u16, usize>,
+}
+
+impl Program {
+    /// Where the code for `line` starts, once for every time it was emitted.
+    pub fn line_addresses(&self, line: usize) -> Vec<u16> {
+        self.lines
+            .iter()
+            .filter(|(address, l)| {
+                **l == line && self.lines.get(&address.wrapping_sub(2)) != Some(&line)
+            })
+            .map(|(address, _)| *address)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<Token>,
+}
+
+#[derive(Clone, Copy)]
+enum FixupKind {
+    /// The low 12 bits of the opcode at the fixup address.
+    Address,
+    /// The whole word following `i := long`.
+    Long,
+}
+
+struct Fixup {
+    address: u16,
+    kind: FixupKind,
+    label: String,
+    line: usize,
+}
+
+enum Block {
+    Loop { start: u16, exits: Vec<u16> },
+    /// The jump taken when the `if` condition doesn't hold.
+    If { jump: u16, has_else: bool },
+}
+
+/// Opcodes that skip the next instruction when a condition holds and when it doesn't.
+struct Condition {
+    skip_if_true: u16,
+    skip_if_false: u16,
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    line: usize,
+    rom: Vec<u8>,
+    here: u16,
+    /// Whether 0x200 still holds the placeholder for `jump main`.
+    main_slot: bool,
+    labels: BTreeMap<String, u16>,
+    lines: BTreeMap<u16, usize>,
+    constants: HashMap<String, i32>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    expansions: usize,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+}
+
+/// Assembles Octo source into the image `Chip8VM::load_rom` expects.
+///
+/// Like Octo, execution starts at the `main` label: a `jump main` is placed at
+/// 0x200 unless `main` is the first thing in the program.
+pub fn assemble(source: &str) -> Result<Program, AssembleError> {
+    let mut assembler = Assembler::new(tokenize(source));
+    while !assembler.tokens.is_empty() {
+        assembler.statement()?;
+    }
+    assembler.finish()
+}
+
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (index, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or_default();
+        for text in code.split_whitespace() {
+            tokens.push_back(Token {
+                text: text.to_string(),
+                line: index + 1,
+            });
+        }
+    }
+    tokens
+}
+
+fn parse_literal(text: &str) -> Option<i32> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i32::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i32::from_str_radix(binary, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix('v').or_else(|| text.strip_prefix('V'))?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+impl Assembler {
+    fn new(tokens: VecDeque<Token>) -> Self {
+        Assembler {
+            tokens,
+            line: 1,
+            // reserved for `jump main`, dropped if main turns out to be right here
+            rom: vec![0x10, 0x00],
+            here: PROGRAM_START_ADDRESS + 2,
+            main_slot: true,
+            labels: BTreeMap::new(),
+            lines: BTreeMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            expansions: 0,
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+        }
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, AssembleError> {
+        Err(AssembleError {
+            line: self.line,
+            message,
+        })
+    }
+
+    fn next(&mut self) -> Result<String, AssembleError> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.line = token.line;
+                Ok(token.text)
+            }
+            None => self.error("unexpected end of file".to_string()),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), AssembleError> {
+        let token = self.next()?;
+        if token != expected {
+            return self.error(format!("expected '{}', found '{}'", expected, token));
+        }
+        Ok(())
+    }
+
+    fn register(&mut self) -> Result<u8, AssembleError> {
+        let token = self.next()?;
+        match parse_register(&token).or_else(|| self.aliases.get(&token).copied()) {
+            Some(register) => Ok(register),
+            None => self.error(format!("expected a register, found '{}'", token)),
+        }
+    }
+
+    fn is_register(&self, token: &str) -> bool {
+        parse_register(token).is_some() || self.aliases.contains_key(token)
+    }
+
+    fn value(&self, token: &str) -> Option<i32> {
+        self.constants.get(token).copied().or_else(|| parse_literal(token))
+    }
+
+    fn number(&mut self, min: i32, max: i32) -> Result<i32, AssembleError> {
+        let token = self.next()?;
+        match self.value(&token) {
+            Some(value) if (min..=max).contains(&value) => Ok(value),
+            Some(value) => self.error(format!("{} is out of range {}..={}", value, min, max)),
+            None => self.error(format!("expected a number, found '{}'", token)),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u16, AssembleError> {
+        Ok((self.number(-128, 255)? & 0xFF) as u16)
+    }
+
+    fn nibble(&mut self) -> Result<u16, AssembleError> {
+        Ok(self.number(0, 15)? as u16)
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), AssembleError> {
+        let offset = match self.here.checked_sub(PROGRAM_START_ADDRESS) {
+            Some(offset) => offset as usize,
+            None => return self.error(format!("0x{:03x} is below 0x200", self.here)),
+        };
+        if self.rom.len() <= offset {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.here = match self.here.checked_add(1) {
+            Some(here) => here,
+            None => return self.error("program doesn't fit in 64K".to_string()),
+        };
+        Ok(())
+    }
+
+    fn emit(&mut self, word: u16) -> Result<(), AssembleError> {
+        self.lines.insert(self.here, self.line);
+        self.emit_byte((word >> 8) as u8)?;
+        self.emit_byte(word as u8)
+    }
+
+    fn patch(&mut self, address: u16, kind: FixupKind, target: u16) {
+        let offset = (address - PROGRAM_START_ADDRESS) as usize;
+        match kind {
+            FixupKind::Address => {
+                self.rom[offset] = (self.rom[offset] & 0xF0) | ((target >> 8) & 0x0F) as u8;
+                self.rom[offset + 1] = target as u8;
+            }
+            FixupKind::Long => {
+                self.rom[offset] = (target >> 8) as u8;
+                self.rom[offset + 1] = target as u8;
+            }
+        }
+    }
+
+    /// Blocks compile to plain jumps, which only reach the first 4K like labels do.
+    fn check_jump(&self, keyword: &str, target: u16) -> Result<(), AssembleError> {
+        if target > 0xFFF {
+            return self.error(format!("'{}' jumps past 0xFFF, use 'i := long'", keyword));
+        }
+        Ok(())
+    }
+
+    /// Emits `opcode` with the address operand that follows, resolved later if it's a
+    /// label that hasn't been defined yet.
+    fn emit_with_address(&mut self, opcode: u16, kind: FixupKind) -> Result<(), AssembleError> {
+        let token = self.next()?;
+        let max = match kind {
+            FixupKind::Address => 0xFFF,
+            FixupKind::Long => 0xFFFF,
+        };
+        let address = match kind {
+            FixupKind::Address => self.here,
+            FixupKind::Long => self.here + 2,
+        };
+        self.emit(opcode)?;
+        if let FixupKind::Long = kind {
+            self.emit(0)?;
+        }
+
+        if let Some(value) = self.value(&token) {
+            if !(0..=max).contains(&value) {
+                return self.error(format!("address 0x{:x} is out of range", value));
+            }
+            self.patch(address, kind, value as u16);
+        } else {
+            self.fixups.push(Fixup {
+                address,
+                kind,
+                label: token,
+                line: self.line,
+            });
+        }
+        Ok(())
+    }
+
+    fn define_label(&mut self, name: String) -> Result<(), AssembleError> {
+        if self.labels.contains_key(&name) {
+            return self.error(format!("label '{}' is already defined", name));
+        }
+        if name == "main" && self.main_slot && self.here == PROGRAM_START_ADDRESS + 2 {
+            self.rom.clear();
+            self.here = PROGRAM_START_ADDRESS;
+            self.main_slot = false;
+            // labels right before main name the same code, which moves back with it
+            for address in self.labels.values_mut() {
+                if *address == PROGRAM_START_ADDRESS + 2 {
+                    *address = PROGRAM_START_ADDRESS;
+                }
+            }
+        }
+        self.labels.insert(name, self.here);
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Condition, AssembleError> {
+        let x = self.register()? as u16;
+        let operator = self.next()?;
+        let (skip_if_true, skip_if_false) = match operator.as_str() {
+            "key" => (0xE09E, 0xE0A1),
+            "-key" => (0xE0A1, 0xE09E),
+            "==" | "!=" => {
+                let (equal, not_equal) = if self.peek().is_some_and(|t| self.is_register(t)) {
+                    let y = self.register()? as u16;
+                    (0x5000 | y << 4, 0x9000 | y << 4)
+                } else {
+                    let nn = self.byte()?;
+                    (0x3000 | nn, 0x4000 | nn)
+                };
+                if operator == "==" {
+                    (equal, not_equal)
+                } else {
+                    (not_equal, equal)
+                }
+            }
+            _ => return self.error(format!("unsupported comparison '{}'", operator)),
+        };
+        Ok(Condition {
+            skip_if_true: skip_if_true | x << 8,
+            skip_if_false: skip_if_false | x << 8,
+        })
+    }
+
+    fn register_statement(&mut self, x: u8) -> Result<(), AssembleError> {
+        let x = (x as u16) << 8;
+        let operator = self.next()?;
+        let rhs_is_register = self.peek().is_some_and(|t| self.is_register(t));
+        let alu = match operator.as_str() {
+            ":=" => 0x0,
+            "|=" => 0x1,
+            "&=" => 0x2,
+            "^=" => 0x3,
+            "+=" => 0x4,
+            "-=" => 0x5,
+            ">>=" => 0x6,
+            "=-" => 0x7,
+            "<<=" => 0xE,
+            _ => return self.error(format!("unknown operator '{}'", operator)),
+        };
+        if rhs_is_register {
+            let y = (self.register()? as u16) << 4;
+            return self.emit(0x8000 | x | y | alu);
+        }
+
+        match (operator.as_str(), self.peek()) {
+            (":=", Some("random")) => {
+                self.next()?;
+                let nn = self.byte()?;
+                self.emit(0xC000 | x | nn)
+            }
+            (":=", Some("delay")) => {
+                self.next()?;
+                self.emit(0xF007 | x)
+            }
+            (":=", Some("key")) => {
+                self.next()?;
+                self.emit(0xF00A | x)
+            }
+            (":=", _) => {
+                let nn = self.byte()?;
+                self.emit(0x6000 | x | nn)
+            }
+            ("+=", _) => {
+                let nn = self.byte()?;
+                self.emit(0x7000 | x | nn)
+            }
+            ("-=", _) => {
+                let nn = self.byte()?;
+                self.emit(0x7000 | x | ((0x100 - nn) & 0xFF))
+            }
+            _ => self.error(format!("'{}' needs a register operand", operator)),
+        }
+    }
+
+    fn i_statement(&mut self) -> Result<(), AssembleError> {
+        let operator = self.next()?;
+        match (operator.as_str(), self.peek()) {
+            ("+=", _) => {
+                let x = self.register()? as u16;
+                self.emit(0xF01E | x << 8)
+            }
+            (":=", Some("hex")) => {
+                self.next()?;
+                let x = self.register()? as u16;
+                self.emit(0xF029 | x << 8)
+            }
+            (":=", Some("bighex")) => {
+                self.next()?;
+                let x = self.register()? as u16;
+                self.emit(0xF030 | x << 8)
+            }
+            (":=", Some("long")) => {
+                self.next()?;
+                self.emit_with_address(0xF000, FixupKind::Long)
+            }
+            (":=", _) => self.emit_with_address(0xA000, FixupKind::Address),
+            _ => self.error(format!("unknown operator '{}' for i", operator)),
+        }
+    }
+
+    fn save_or_load(&mut self, single: u16, range: u16) -> Result<(), AssembleError> {
+        let x = self.register()? as u16;
+        if self.peek() == Some("-") {
+            self.next()?;
+            let y = self.register()? as u16;
+            return self.emit(range | x << 8 | y << 4);
+        }
+        self.emit(single | x << 8)
+    }
+
+    fn directive(&mut self, directive: &str) -> Result<(), AssembleError> {
+        match directive {
+            ":" => {
+                let name = self.next()?;
+                self.define_label(name)
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let register = self.register()?;
+                self.aliases.insert(name, register);
+                Ok(())
+            }
+            ":const" => {
+                let name = self.next()?;
+                let value = self.number(i32::MIN, i32::MAX)?;
+                self.constants.insert(name, value);
+                Ok(())
+            }
+            ":macro" => {
+                let name = self.next()?;
+                let mut parameters = Vec::new();
+                loop {
+                    let token = self.next()?;
+                    if token == "{" {
+                        break;
+                    }
+                    parameters.push(token);
+                }
+                let mut body = Vec::new();
+                let mut depth = 1;
+                loop {
+                    let Some(token) = self.tokens.pop_front() else {
+                        return self.error(format!("macro '{}' is missing a '}}'", name));
+                    };
+                    match token.text.as_str() {
+                        "{" => depth += 1,
+                        "}" => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                    body.push(token);
+                }
+                self.macros.insert(name, Macro { parameters, body });
+                Ok(())
+            }
+            ":call" => self.emit_with_address(0x2000, FixupKind::Address),
+            ":byte" => {
+                let byte = self.byte()?;
+                self.emit_byte(byte as u8)
+            }
+            ":org" => {
+                self.here = self.number(PROGRAM_START_ADDRESS as i32, 0xFFFF)? as u16;
+                Ok(())
+            }
+            _ => self.error(format!("unknown directive '{}'", directive)),
+        }
+    }
+
+    fn expand_macro(&mut self, name: &str) -> Result<(), AssembleError> {
+        self.expansions += 1;
+        if self.expansions > MAX_MACRO_EXPANSIONS {
+            return self.error(format!("macro '{}' expands forever", name));
+        }
+        let parameter_count = self.macros[name].parameters.len();
+        let mut arguments = HashMap::new();
+        for index in 0..parameter_count {
+            let argument = self.next()?;
+            arguments.insert(self.macros[name].parameters[index].clone(), argument);
+        }
+        let expansion = &self.macros[name].body;
+        for token in expansion.iter().rev() {
+            let text = arguments.get(&token.text).unwrap_or(&token.text).clone();
+            self.tokens.push_front(Token {
+                text,
+                line: token.line,
+            });
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), AssembleError> {
+        let token = self.next()?;
+        if token.starts_with(':') {
+            return self.directive(&token);
+        }
+        if self.macros.contains_key(&token) {
+            return self.expand_macro(&token);
+        }
+        if let Some(x) = parse_register(&token).or_else(|| self.aliases.get(&token).copied()) {
+            return self.register_statement(x);
+        }
+        if let Some(value) = self.value(&token) {
+            if !(-128..=255).contains(&value) {
+                return self.error(format!("{} doesn't fit in a byte", value));
+            }
+            return self.emit_byte(value as u8);
+        }
+
+        match token.as_str() {
+            "clear" => self.emit(0x00E0),
+            "return" | ";" => self.emit(0x00EE),
+            "exit" => self.emit(0x00FD),
+            "lores" => self.emit(0x00FE),
+            "hires" => self.emit(0x00FF),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(0x00C0 | n)
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(0x00D0 | n)
+            }
+            "scroll-right" => self.emit(0x00FB),
+            "scroll-left" => self.emit(0x00FC),
+            "jump" => self.emit_with_address(0x1000, FixupKind::Address),
+            "jump0" => self.emit_with_address(0xB000, FixupKind::Address),
+            "sprite" => {
+                let x = self.register()? as u16;
+                let y = self.register()? as u16;
+                let n = self.nibble()?;
+                self.emit(0xD000 | x << 8 | y << 4 | n)
+            }
+            "bcd" => {
+                let x = self.register()? as u16;
+                self.emit(0xF033 | x << 8)
+            }
+            "save" => self.save_or_load(0xF055, 0x5002),
+            "load" => self.save_or_load(0xF065, 0x5003),
+            "saveflags" => {
+                let x = self.register()? as u16;
+                self.emit(0xF075 | x << 8)
+            }
+            "loadflags" => {
+                let x = self.register()? as u16;
+                self.emit(0xF085 | x << 8)
+            }
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(0xF001 | n << 8)
+            }
+            "audio" => self.emit(0xF002),
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()? as u16;
+                let opcode = match token.as_str() {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                };
+                self.emit(opcode | x << 8)
+            }
+            "i" => self.i_statement(),
+            "if" => {
+                let condition = self.condition()?;
+                match self.next()?.as_str() {
+                    "then" => self.emit(condition.skip_if_false),
+                    "begin" => {
+                        self.emit(condition.skip_if_true)?;
+                        self.blocks.push(Block::If {
+                            jump: self.here,
+                            has_else: false,
+                        });
+                        self.emit(0x1000)
+                    }
+                    other => self.error(format!("expected 'then' or 'begin', found '{}'", other)),
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::If {
+                    jump,
+                    has_else: false,
+                }) => {
+                    let else_jump = self.here;
+                    self.emit(0x1000)?;
+                    self.check_jump("else", self.here)?;
+                    self.patch(jump, FixupKind::Address, self.here);
+                    self.blocks.push(Block::If {
+                        jump: else_jump,
+                        has_else: true,
+                    });
+                    Ok(())
+                }
+                _ => self.error("'else' without 'if ... begin'".to_string()),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump, .. }) => {
+                    self.check_jump("end", self.here)?;
+                    self.patch(jump, FixupKind::Address, self.here);
+                    Ok(())
+                }
+                _ => self.error("'end' without 'if ... begin'".to_string()),
+            },
+            "loop" => {
+                self.blocks.push(Block::Loop {
+                    start: self.here,
+                    exits: Vec::new(),
+                });
+                Ok(())
+            }
+            "while" => {
+                let condition = self.condition()?;
+                self.emit(condition.skip_if_true)?;
+                let exit = self.here;
+                match self.blocks.iter_mut().rev().find(|b| matches!(b, Block::Loop { .. })) {
+                    Some(Block::Loop { exits, .. }) => exits.push(exit),
+                    _ => return self.error("'while' outside of a loop".to_string()),
+                }
+                self.emit(0x1000)
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, exits }) => {
+                    self.check_jump("again", start)?;
+                    self.emit(0x1000 | start)?;
+                    if !exits.is_empty() {
+                        self.check_jump("while", self.here)?;
+                    }
+                    for exit in exits {
+                        self.patch(exit, FixupKind::Address, self.here);
+                    }
+                    Ok(())
+                }
+                _ => self.error("'again' without 'loop'".to_string()),
+            },
+            // anything else names a subroutine to call
+            _ => {
+                self.tokens.push_front(Token {
+                    text: token,
+                    line: self.line,
+                });
+                self.emit_with_address(0x2000, FixupKind::Address)
+            }
+        }
+    }
+
+    fn finish(mut self) -> Result<Program, AssembleError> {
+        if let Some(block) = self.blocks.last() {
+            let open = match block {
+                Block::Loop { .. } => "loop",
+                Block::If { .. } => "if ... begin",
+            };
+            return self.error(format!("'{}' is never closed", open));
+        }
+
+        for fixup in std::mem::take(&mut self.fixups) {
+            let Some(&target) = self.labels.get(&fixup.label) else {
+                return Err(AssembleError {
+                    line: fixup.line,
+                    message: format!("undefined label '{}'", fixup.label),
+                });
+            };
+            if let FixupKind::Address = fixup.kind {
+                if target > 0xFFF {
+                    return Err(AssembleError {
+                        line: fixup.line,
+                        message: format!("label '{}' is past 0xFFF, use 'i := long'", fixup.label),
+                    });
+                }
+            }
+            self.patch(fixup.address, fixup.kind, target);
+        }
+
+        if self.main_slot {
+            match self.labels.get("main") {
+                Some(&main) => self.patch(PROGRAM_START_ADDRESS, FixupKind::Address, main),
+                None => return self.error("program is missing a 'main' label".to_string()),
+            }
+        }
+
+        Ok(Program {
+            bytes: self.rom,
+            labels: self.labels,
+            lines: self.lines,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8_vm::quirks::Platform;
+    use crate::disassembler::disassemble_bytes;
+
+    fn bytes(source: &str) -> Vec<u8> {
+        assemble(source).unwrap().bytes
+    }
+
+    #[test]
+    fn test_statements() {
+        let source = "
+            : main
+                clear
+                v0 := 5
+                va += 0x2a
+                v1 -= 1
+                v2 =- v3
+                i := hex v0
+                sprite v0 v1 5
+                save v4
+                load v1 - v3
+                delay := v0
+                v0 := random 0b1111";
+        assert_eq!(
+            bytes(source),
+            vec![
+                0x00, 0xE0, 0x60, 0x05, 0x7A, 0x2A, 0x71, 0xFF, 0x82, 0x37, 0xF0, 0x29, 0xD0,
+                0x15, 0xF4, 0x55, 0x51, 0x33, 0xF0, 0x15, 0xC0, 0x0F
+            ]
+        );
+    }
+
+    #[test]
+    fn test_labels_and_main() {
+        // main isn't first, so 0x200 jumps to it
+        let program = assemble(": data 0xFF : main i := data loop again").unwrap();
+        assert_eq!(program.bytes, vec![0x12, 0x03, 0xFF, 0xA2, 0x02, 0x12, 0x05]);
+        assert_eq!(program.labels["data"], 0x202);
+
+        // labels on the same spot as main move with it when the slot is dropped
+        let program = assemble(": other : main jump other").unwrap();
+        assert_eq!(program.bytes, vec![0x12, 0x00]);
+        assert_eq!(program.labels["other"], 0x200);
+        let program = assemble(": sprite : main i := sprite").unwrap();
+        assert_eq!(program.bytes, vec![0xA2, 0x00]);
+        assert_eq!(program.labels["sprite"], 0x200);
+
+        // forward references to a subroutine
+        assert_eq!(
+            bytes(": main draw jump main : draw return"),
+            vec![0x22, 0x04, 0x12, 0x00, 0x00, 0xEE]
+        );
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let source = "
+            :alias counter v3
+            :const LIMIT 10
+            : main
+                loop
+                    counter += 1
+                    if counter == LIMIT then jump done
+                    while counter != 20
+                    if counter key begin
+                        v0 := 1
+                    else
+                        v0 := 2
+                    end
+                again
+            : done";
+        assert_eq!(
+            bytes(source),
+            vec![
+                0x73, 0x01, 0x43, 0x0A, 0x12, 0x16, 0x43, 0x14, 0x12, 0x16, 0xE3, 0x9E, 0x12,
+                0x12, 0x60, 0x01, 0x12, 0x14, 0x60, 0x02, 0x12, 0x00
+            ]
+        );
+    }
+
+    #[test]
+    fn test_macros() {
+        let source = "
+            :macro swap a b { vf := a a := b b := vf }
+            : main swap v1 v2";
+        assert_eq!(bytes(source), vec![0x8F, 0x10, 0x81, 0x20, 0x82, 0xF0]);
+    }
+
+    #[test]
+    fn test_source_lines() {
+        let source = "
+            :macro twice { v0 += 1 v0 += 1 }
+            : main
+                clear
+                twice
+                if v0 == 2 begin twice end
+                jump main";
+        let program = assemble(source).unwrap();
+        assert_eq!(program.lines[&0x200], 4);
+        assert_eq!(program.lines[&0x202], 2);
+        assert_eq!(program.line_addresses(2), vec![0x202, 0x20A]);
+        assert_eq!(program.line_addresses(6), vec![0x206]);
+        assert_eq!(program.line_addresses(7), vec![0x20E]);
+        assert!(program.line_addresses(3).is_empty());
+    }
+
+    #[test]
+    fn test_errors_have_line_numbers() {
+        let error = assemble(": main\n  v0 := 300\n").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert_eq!(error.to_string(), "line 2: 300 is out of range -128..=255");
+
+        let error = assemble(": main\n\n  jump nowhere").unwrap_err();
+        assert_eq!(error.to_string(), "line 3: undefined label 'nowhere'");
+
+        let error = assemble("clear").unwrap_err();
+        assert_eq!(error.message, "program is missing a 'main' label");
+
+        let error = assemble(": main\nloop\nclear\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 3: 'loop' is never closed");
+
+        let error = assemble(": main\nif v0 == 1 begin\n:org 0x1000\nend").unwrap_err();
+        assert_eq!(error.to_string(), "line 4: 'end' jumps past 0xFFF, use 'i := long'");
+
+        let error = assemble(": main\n:org 0x1000\nloop\nagain").unwrap_err();
+        assert_eq!(error.to_string(), "line 4: 'again' jumps past 0xFFF, use 'i := long'");
+    }
+
+    #[test]
+    fn test_round_trips_disassembly() {
+        let rom = include_bytes!("../../roms/particles.ch8");
+        let listing = disassemble_bytes(rom, Platform::Chip8).to_string();
+        assert_eq!(assemble(&listing).unwrap().bytes, rom.to_vec());
+    }
+}
@@ -1,6 +1,18 @@
-
 pub const SCREEN_WIDTH: usize = 64;
 pub const SCREEN_HEIGHT: usize = 32;
+pub const HIRES_SCREEN_WIDTH: usize = 128;
+pub const HIRES_SCREEN_HEIGHT: usize = 64;
+pub const NUM_PLANES: usize = 2;
+
+/// RGBA colors for each combination of the two planes: none, first, second, both.
+pub type Palette = [[u8; 4]; 1 << NUM_PLANES];
+
+pub const DEFAULT_PALETTE: Palette = [
+    [0, 0, 0, 255],
+    [255, 255, 255, 255],
+    [170, 170, 170, 255],
+    [85, 85, 85, 255],
+];
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DisplayState {
@@ -9,39 +21,132 @@ pub enum DisplayState {
     Noop,
 }
 pub struct Display {
-    pub buffer: [[u8; SCREEN_WIDTH]; SCREEN_HEIGHT],
+    /// Sized for hi-res, in lo-res mode only the top-left 64x32 pixels are used.
+    /// Each pixel holds one bit per plane.
+    pub buffer: [[u8; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT],
     pub state: DisplayState,
+    hires: bool,
+    plane_mask: u8,
+    palette: Palette,
 }
 
 impl Display {
 
     pub fn new() -> Self {
         Display {
-            buffer: [[0; SCREEN_WIDTH]; SCREEN_HEIGHT],
+            buffer: [[0; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT],
             state: DisplayState::Noop,
+            hires: false,
+            plane_mask: 1,
+            palette: DEFAULT_PALETTE,
         }
     }
 
     pub fn clear(&mut self) {
-        self.buffer = [[0; SCREEN_WIDTH]; SCREEN_HEIGHT];
+        self.buffer = [[0; HIRES_SCREEN_WIDTH]; HIRES_SCREEN_HEIGHT];
+    }
+
+    /// Clears only the planes selected by the plane mask.
+    pub fn clear_planes(&mut self) {
+        for row in self.buffer.iter_mut() {
+            for pixel in row.iter_mut() {
+                *pixel &= !self.plane_mask;
+            }
+        }
+    }
+
+    pub fn plane_mask(&self) -> u8 {
+        self.plane_mask
+    }
+
+    /// Selects the planes affected by drawing, clearing and scrolling.
+    pub fn set_plane_mask(&mut self, mask: u8) {
+        self.plane_mask = mask & ((1 << NUM_PLANES) - 1);
     }
 
-    pub fn draw_pixel(&mut self, x: usize, y: usize) {
+    pub fn palette(&self) -> Palette {
+        self.palette
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.state = DisplayState::Draw;
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Switches between 64x32 and 128x64, clearing the screen.
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.clear();
+        self.state = DisplayState::Clear;
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires {
+            HIRES_SCREEN_WIDTH
+        } else {
+            SCREEN_WIDTH
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires {
+            HIRES_SCREEN_HEIGHT
+        } else {
+            SCREEN_HEIGHT
+        }
+    }
+
+    /// Toggles the pixel at (x, y) in the planes set in `plane`.
+    pub fn draw_pixel(&mut self, x: usize, y: usize, plane: u8) {
         self.state = DisplayState::Draw;
         let pixel = &mut self.buffer[y][x];
-        *pixel ^= 1;
+        *pixel ^= plane;
+    }
+
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.scroll(0, lines as isize);
+    }
+
+    pub fn scroll_up(&mut self, lines: usize) {
+        self.scroll(0, -(lines as isize));
+    }
+
+    pub fn scroll_left(&mut self, columns: usize) {
+        self.scroll(-(columns as isize), 0);
+    }
+
+    pub fn scroll_right(&mut self, columns: usize) {
+        self.scroll(columns as isize, 0);
+    }
+
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let source = self.buffer;
+        for y in 0..height {
+            for x in 0..width {
+                let (source_x, source_y) = (x - dx, y - dy);
+                let moved = if (0..width).contains(&source_x) && (0..height).contains(&source_y) {
+                    source[source_y as usize][source_x as usize]
+                } else {
+                    0
+                };
+                let pixel = &mut self.buffer[y as usize][x as usize];
+                *pixel = (*pixel & !self.plane_mask) | (moved & self.plane_mask);
+            }
+        }
+        self.state = DisplayState::Draw;
     }
 
     pub fn get_color_buffer(&mut self) -> Vec<u8> {
         let mut color_buffer = Vec::new();
     
-        for row in &self.buffer {
-            for pixel in row {
-                if *pixel == 0 {
-                    color_buffer.extend_from_slice(&[0, 0, 0, 255]);
-                } else {
-                    color_buffer.extend_from_slice(&[255, 255, 255, 255]);
-                }
+        for row in self.buffer.iter().take(self.height()) {
+            for pixel in row.iter().take(self.width()) {
+                color_buffer.extend_from_slice(&self.palette[*pixel as usize]);
             }
         }
     
@@ -0,0 +1,29 @@
+use std::fmt;
+
+/// Faults that halt the VM until it is reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmError {
+    UnknownOpcode { pc: u16, opcode: u16 },
+    StackOverflow { pc: u16 },
+    StackUnderflow { pc: u16 },
+    MemoryOutOfBounds { pc: u16, addr: usize },
+    PcOutOfBounds { pc: u16 },
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmError::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode {:04X} at {:03X}", opcode, pc)
+            }
+            VmError::StackOverflow { pc } => write!(f, "stack overflow at {:03X}", pc),
+            VmError::StackUnderflow { pc } => write!(f, "stack underflow at {:03X}", pc),
+            VmError::MemoryOutOfBounds { pc, addr } => {
+                write!(f, "memory access out of bounds at {:03X}: {:X}", pc, addr)
+            }
+            VmError::PcOutOfBounds { pc } => write!(f, "program counter out of bounds: {:X}", pc),
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
@@ -0,0 +1,287 @@
+use std::fmt;
+
+use super::quirks::Platform;
+
+/// A decoded opcode with its operands: `x`/`y` register indices, `n` nibble,
+/// `nn` byte and `nnn` address.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Instruction {
+    ClearScreen,
+    Return,
+    ScrollDown { n: u8 },
+    ScrollUp { n: u8 },
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    LowResolution,
+    HighResolution,
+    Jump { nnn: u16 },
+    Call { nnn: u16 },
+    SkipIfEqual { x: u8, nn: u8 },
+    SkipIfNotEqual { x: u8, nn: u8 },
+    SkipIfVxEqualVy { x: u8, y: u8 },
+    SaveRange { x: u8, y: u8 },
+    LoadRange { x: u8, y: u8 },
+    SetVx { x: u8, nn: u8 },
+    AddVx { x: u8, nn: u8 },
+    SetVxVy { x: u8, y: u8 },
+    SetVxOrVy { x: u8, y: u8 },
+    SetVxAndVy { x: u8, y: u8 },
+    SetVxXorVy { x: u8, y: u8 },
+    AddVxVy { x: u8, y: u8 },
+    SubVxVy { x: u8, y: u8 },
+    ShiftRight { x: u8, y: u8 },
+    SubVyVx { x: u8, y: u8 },
+    ShiftLeft { x: u8, y: u8 },
+    SkipIfVxNotVy { x: u8, y: u8 },
+    SetI { nnn: u16 },
+    /// `x` is the high nibble of `nnn`, used instead of V0 by the jump quirk.
+    JumpV0 { x: u8, nnn: u16 },
+    Random { x: u8, nn: u8 },
+    Draw { x: u8, y: u8, n: u8 },
+    SkipIfPressed { x: u8 },
+    SkipIfNotPressed { x: u8 },
+    /// F000 NNNN, the address is in the word following the opcode.
+    SetLongI,
+    /// FN01, `x` is the plane mask.
+    SelectPlanes { x: u8 },
+    LoadAudioPattern,
+    SetVxToDelayTimer { x: u8 },
+    WaitForKeyPress { x: u8 },
+    SetDelayTimer { x: u8 },
+    SetSoundTimer { x: u8 },
+    AddVxToI { x: u8 },
+    SetIToSprite { x: u8 },
+    SetIToBigSprite { x: u8 },
+    StoreBCD { x: u8 },
+    SetPitch { x: u8 },
+    StoreRegisters { x: u8 },
+    LoadRegisters { x: u8 },
+    StoreFlags { x: u8 },
+    LoadFlags { x: u8 },
+    Unknown { opcode: u16 },
+}
+
+pub fn decode(opcode: u16) -> Instruction {
+    let x = ((opcode & 0x0F00) >> 8) as u8;
+    let y = ((opcode & 0x00F0) >> 4) as u8;
+    let n = (opcode & 0x000F) as u8;
+    let nn = (opcode & 0x00FF) as u8;
+    let nnn = opcode & 0x0FFF;
+
+    match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00E0 => Instruction::ClearScreen,
+            0x00EE => Instruction::Return,
+            0x00C0..=0x00CF => Instruction::ScrollDown { n },
+            0x00D0..=0x00DF => Instruction::ScrollUp { n },
+            0x00FB => Instruction::ScrollRight,
+            0x00FC => Instruction::ScrollLeft,
+            0x00FD => Instruction::Exit,
+            0x00FE => Instruction::LowResolution,
+            0x00FF => Instruction::HighResolution,
+            _ => Instruction::Unknown { opcode },
+        },
+        0x1000 => Instruction::Jump { nnn },
+        0x2000 => Instruction::Call { nnn },
+        0x3000 => Instruction::SkipIfEqual { x, nn },
+        0x4000 => Instruction::SkipIfNotEqual { x, nn },
+        0x5000 => match n {
+            0x0 => Instruction::SkipIfVxEqualVy { x, y },
+            0x2 => Instruction::SaveRange { x, y },
+            0x3 => Instruction::LoadRange { x, y },
+            _ => Instruction::Unknown { opcode },
+        },
+        0x6000 => Instruction::SetVx { x, nn },
+        0x7000 => Instruction::AddVx { x, nn },
+        0x8000 => match n {
+            0x0 => Instruction::SetVxVy { x, y },
+            0x1 => Instruction::SetVxOrVy { x, y },
+            0x2 => Instruction::SetVxAndVy { x, y },
+            0x3 => Instruction::SetVxXorVy { x, y },
+            0x4 => Instruction::AddVxVy { x, y },
+            0x5 => Instruction::SubVxVy { x, y },
+            0x6 => Instruction::ShiftRight { x, y },
+            0x7 => Instruction::SubVyVx { x, y },
+            0xE => Instruction::ShiftLeft { x, y },
+            _ => Instruction::Unknown { opcode },
+        },
+        0x9000 => match n {
+            0x0 => Instruction::SkipIfVxNotVy { x, y },
+            _ => Instruction::Unknown { opcode },
+        },
+        0xA000 => Instruction::SetI { nnn },
+        0xB000 => Instruction::JumpV0 { x, nnn },
+        0xC000 => Instruction::Random { x, nn },
+        0xD000 => Instruction::Draw { x, y, n },
+        0xE000 => match nn {
+            0x9E => Instruction::SkipIfPressed { x },
+            0xA1 => Instruction::SkipIfNotPressed { x },
+            _ => Instruction::Unknown { opcode },
+        },
+        _ => match nn {
+            0x00 if x == 0 => Instruction::SetLongI,
+            0x01 => Instruction::SelectPlanes { x },
+            0x02 if x == 0 => Instruction::LoadAudioPattern,
+            0x07 => Instruction::SetVxToDelayTimer { x },
+            0x0A => Instruction::WaitForKeyPress { x },
+            0x15 => Instruction::SetDelayTimer { x },
+            0x18 => Instruction::SetSoundTimer { x },
+            0x1E => Instruction::AddVxToI { x },
+            0x29 => Instruction::SetIToSprite { x },
+            0x30 => Instruction::SetIToBigSprite { x },
+            0x33 => Instruction::StoreBCD { x },
+            0x3A => Instruction::SetPitch { x },
+            0x55 => Instruction::StoreRegisters { x },
+            0x65 => Instruction::LoadRegisters { x },
+            0x75 => Instruction::StoreFlags { x },
+            0x85 => Instruction::LoadFlags { x },
+            _ => Instruction::Unknown { opcode },
+        },
+    }
+}
+
+impl Instruction {
+    /// The first platform whose instruction set includes this instruction,
+    /// `None` for the original CHIP-8 instructions.
+    pub fn extension(&self) -> Option<Platform> {
+        match self {
+            Instruction::ScrollDown { .. }
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::Exit
+            | Instruction::LowResolution
+            | Instruction::HighResolution
+            | Instruction::SetIToBigSprite { .. }
+            | Instruction::StoreFlags { .. }
+            | Instruction::LoadFlags { .. } => Some(Platform::SuperChip),
+            Instruction::ScrollUp { .. }
+            | Instruction::SaveRange { .. }
+            | Instruction::LoadRange { .. }
+            | Instruction::SetLongI
+            | Instruction::SelectPlanes { .. }
+            | Instruction::LoadAudioPattern
+            | Instruction::SetPitch { .. } => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    /// Length in bytes, only F000 NNNN takes two words.
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::SetLongI => 4,
+            _ => 2,
+        }
+    }
+
+    /// Whether the instruction may skip the one following it.
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            self,
+            Instruction::SkipIfEqual { .. }
+                | Instruction::SkipIfNotEqual { .. }
+                | Instruction::SkipIfVxEqualVy { .. }
+                | Instruction::SkipIfVxNotVy { .. }
+                | Instruction::SkipIfPressed { .. }
+                | Instruction::SkipIfNotPressed { .. }
+        )
+    }
+}
+
+/// Formats the instruction in Octo syntax.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::ClearScreen => write!(f, "clear"),
+            Instruction::Return => write!(f, "return"),
+            Instruction::ScrollDown { n } => write!(f, "scroll-down {}", n),
+            Instruction::ScrollUp { n } => write!(f, "scroll-up {}", n),
+            Instruction::ScrollRight => write!(f, "scroll-right"),
+            Instruction::ScrollLeft => write!(f, "scroll-left"),
+            Instruction::Exit => write!(f, "exit"),
+            Instruction::LowResolution => write!(f, "lores"),
+            Instruction::HighResolution => write!(f, "hires"),
+            Instruction::Jump { nnn } => write!(f, "jump 0x{:03x}", nnn),
+            Instruction::Call { nnn } => write!(f, ":call 0x{:03x}", nnn),
+            // skips read the other way around in Octo: the next line runs if the condition holds
+            Instruction::SkipIfEqual { x, nn } => write!(f, "if v{:x} != 0x{:02x} then", x, nn),
+            Instruction::SkipIfNotEqual { x, nn } => write!(f, "if v{:x} == 0x{:02x} then", x, nn),
+            Instruction::SkipIfVxEqualVy { x, y } => write!(f, "if v{:x} != v{:x} then", x, y),
+            Instruction::SaveRange { x, y } => write!(f, "save v{:x} - v{:x}", x, y),
+            Instruction::LoadRange { x, y } => write!(f, "load v{:x} - v{:x}", x, y),
+            Instruction::SetVx { x, nn } => write!(f, "v{:x} := 0x{:02x}", x, nn),
+            Instruction::AddVx { x, nn } => write!(f, "v{:x} += 0x{:02x}", x, nn),
+            Instruction::SetVxVy { x, y } => write!(f, "v{:x} := v{:x}", x, y),
+            Instruction::SetVxOrVy { x, y } => write!(f, "v{:x} |= v{:x}", x, y),
+            Instruction::SetVxAndVy { x, y } => write!(f, "v{:x} &= v{:x}", x, y),
+            Instruction::SetVxXorVy { x, y } => write!(f, "v{:x} ^= v{:x}", x, y),
+            Instruction::AddVxVy { x, y } => write!(f, "v{:x} += v{:x}", x, y),
+            Instruction::SubVxVy { x, y } => write!(f, "v{:x} -= v{:x}", x, y),
+            Instruction::ShiftRight { x, y } => write!(f, "v{:x} >>= v{:x}", x, y),
+            Instruction::SubVyVx { x, y } => write!(f, "v{:x} =- v{:x}", x, y),
+            Instruction::ShiftLeft { x, y } => write!(f, "v{:x} <<= v{:x}", x, y),
+            Instruction::SkipIfVxNotVy { x, y } => write!(f, "if v{:x} == v{:x} then", x, y),
+            Instruction::SetI { nnn } => write!(f, "i := 0x{:03x}", nnn),
+            Instruction::JumpV0 { nnn, .. } => write!(f, "jump0 0x{:03x}", nnn),
+            Instruction::Random { x, nn } => write!(f, "v{:x} := random 0x{:02x}", x, nn),
+            Instruction::Draw { x, y, n } => write!(f, "sprite v{:x} v{:x} {}", x, y, n),
+            Instruction::SkipIfPressed { x } => write!(f, "if v{:x} -key then", x),
+            Instruction::SkipIfNotPressed { x } => write!(f, "if v{:x} key then", x),
+            Instruction::SetLongI => write!(f, "i := long"),
+            Instruction::SelectPlanes { x } => write!(f, "plane {}", x),
+            Instruction::LoadAudioPattern => write!(f, "audio"),
+            Instruction::SetVxToDelayTimer { x } => write!(f, "v{:x} := delay", x),
+            Instruction::WaitForKeyPress { x } => write!(f, "v{:x} := key", x),
+            Instruction::SetDelayTimer { x } => write!(f, "delay := v{:x}", x),
+            Instruction::SetSoundTimer { x } => write!(f, "buzzer := v{:x}", x),
+            Instruction::AddVxToI { x } => write!(f, "i += v{:x}", x),
+            Instruction::SetIToSprite { x } => write!(f, "i := hex v{:x}", x),
+            Instruction::SetIToBigSprite { x } => write!(f, "i := bighex v{:x}", x),
+            Instruction::StoreBCD { x } => write!(f, "bcd v{:x}", x),
+            Instruction::SetPitch { x } => write!(f, "pitch := v{:x}", x),
+            Instruction::StoreRegisters { x } => write!(f, "save v{:x}", x),
+            Instruction::LoadRegisters { x } => write!(f, "load v{:x}", x),
+            Instruction::StoreFlags { x } => write!(f, "saveflags v{:x}", x),
+            Instruction::LoadFlags { x } => write!(f, "loadflags v{:x}", x),
+            Instruction::Unknown { opcode } => {
+                write!(f, "0x{:02x} 0x{:02x}", opcode >> 8, opcode & 0xFF)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_operands() {
+        assert_eq!(decode(0x00E0), Instruction::ClearScreen);
+        assert_eq!(decode(0x2ABC), Instruction::Call { nnn: 0xABC });
+        assert_eq!(decode(0x8AB6), Instruction::ShiftRight { x: 0xA, y: 0xB });
+        assert_eq!(decode(0xB3FF), Instruction::JumpV0 { x: 0x3, nnn: 0x3FF });
+        assert_eq!(decode(0xD12F), Instruction::Draw { x: 1, y: 2, n: 0xF });
+        assert_eq!(decode(0xF000), Instruction::SetLongI);
+        assert_eq!(decode(0xF100), Instruction::Unknown { opcode: 0xF100 });
+        assert_eq!(decode(0x5121), Instruction::Unknown { opcode: 0x5121 });
+    }
+
+    #[test]
+    fn test_extensions() {
+        assert_eq!(decode(0x6000).extension(), None);
+        assert_eq!(decode(0x00FF).extension(), Some(Platform::SuperChip));
+        assert_eq!(decode(0xF201).extension(), Some(Platform::XoChip));
+        assert_eq!(decode(0xF000).size(), 4);
+        assert!(decode(0xE1A1).is_skip());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(decode(0x6A2A).to_string(), "va := 0x2a");
+        assert_eq!(decode(0x3105).to_string(), "if v1 != 0x05 then");
+        assert_eq!(decode(0xA22A).to_string(), "i := 0x22a");
+        assert_eq!(decode(0xD015).to_string(), "sprite v0 v1 5");
+        assert_eq!(decode(0xFF65).to_string(), "load vf");
+        assert_eq!(decode(0x0123).to_string(), "0x01 0x23");
+    }
+}
@@ -1,5 +1,11 @@
 pub mod display;
+pub mod error;
+pub mod instruction;
 pub mod keyboard;
+pub mod quirks;
+pub mod rng;
+pub mod savestate;
+pub mod timing;
 pub mod vm;
 mod stack;
 mod timers;
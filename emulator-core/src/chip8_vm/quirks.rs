@@ -0,0 +1,137 @@
+/// Platforms whose interpreters disagree on the ambiguous opcodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    /// The original COSMAC VIP interpreter.
+    Chip8,
+    /// CHIP-48 on the HP-48 calculators.
+    Chip48,
+    /// SUPER-CHIP 1.1.
+    SuperChip,
+    /// Octo's XO-CHIP.
+    XoChip,
+}
+
+impl std::str::FromStr for Platform {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "chip8" | "chip-8" => Ok(Platform::Chip8),
+            "chip48" | "chip-48" => Ok(Platform::Chip48),
+            "schip" | "superchip" | "super-chip" => Ok(Platform::SuperChip),
+            "xochip" | "xo-chip" => Ok(Platform::XoChip),
+            _ => Err(format!("unknown platform '{}'", name)),
+        }
+    }
+}
+
+impl TryFrom<u8> for Platform {
+    type Error = u8;
+
+    fn try_from(id: u8) -> Result<Self, Self::Error> {
+        match id {
+            0 => Ok(Platform::Chip8),
+            1 => Ok(Platform::Chip48),
+            2 => Ok(Platform::SuperChip),
+            3 => Ok(Platform::XoChip),
+            _ => Err(id),
+        }
+    }
+}
+
+/// Number of fields in `Quirks`.
+pub const NUM_QUIRKS: usize = 6;
+
+/// Behaviors that differ between CHIP-8 interpreters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE copy Vy into Vx before shifting instead of shifting Vx in place.
+    pub shift_uses_vy: bool,
+    /// FX55/FX65 leave I pointing past the last register stored or loaded.
+    pub load_store_increments_i: bool,
+    /// BNNN jumps to NNN + Vx (X being the high nibble of NNN) instead of NNN + V0.
+    pub jump_uses_vx: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0.
+    pub logic_resets_vf: bool,
+    /// DXYN clips sprites at the screen edges instead of wrapping them around.
+    pub clip_sprites: bool,
+    /// DXYN waits for the vertical blank, limiting drawing to one sprite per frame.
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    pub fn from_platform(platform: Platform) -> Self {
+        match platform {
+            Platform::Chip8 => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                logic_resets_vf: true,
+                clip_sprites: true,
+                display_wait: true,
+            },
+            Platform::Chip48 => Quirks {
+                shift_uses_vy: false,
+                load_store_increments_i: false,
+                jump_uses_vx: true,
+                logic_resets_vf: false,
+                clip_sprites: true,
+                display_wait: false,
+            },
+            Platform::SuperChip => Quirks {
+                shift_uses_vy: false,
+                load_store_increments_i: false,
+                jump_uses_vx: true,
+                logic_resets_vf: false,
+                clip_sprites: true,
+                display_wait: false,
+            },
+            Platform::XoChip => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                logic_resets_vf: false,
+                clip_sprites: false,
+                display_wait: false,
+            },
+        }
+    }
+}
+
+impl Quirks {
+    /// One byte per quirk in declaration order, as stored in save states and movies.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        vec![
+            self.shift_uses_vy as u8,
+            self.load_store_increments_i as u8,
+            self.jump_uses_vx as u8,
+            self.logic_resets_vf as u8,
+            self.clip_sprites as u8,
+            self.display_wait as u8,
+        ]
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let flag = |index: usize| bytes.get(index).map(|value| *value != 0);
+        Some(Quirks {
+            shift_uses_vy: flag(0)?,
+            load_store_increments_i: flag(1)?,
+            jump_uses_vx: flag(2)?,
+            logic_resets_vf: flag(3)?,
+            clip_sprites: flag(4)?,
+            display_wait: flag(5)?,
+        })
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::from_platform(Platform::Chip8)
+    }
+}
+
+impl From<Platform> for Quirks {
+    fn from(platform: Platform) -> Self {
+        Quirks::from_platform(platform)
+    }
+}
@@ -0,0 +1,66 @@
+/// Source of the random bytes CXNN masks.
+///
+/// The whole state must fit in a `u64` so snapshots and movies can restore the
+/// exact sequence.
+pub trait RandomSource {
+    fn next_byte(&mut self) -> u8;
+    fn seed(&mut self, seed: u64);
+    fn state(&self) -> u64;
+    fn set_state(&mut self, state: u64);
+}
+
+/// SplitMix64, small and good enough for games, with any seed (including 0) usable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        SplitMix64 { state: seed }
+    }
+}
+
+impl RandomSource for SplitMix64 {
+    fn next_byte(&mut self) -> u8 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        ((z ^ (z >> 31)) >> 56) as u8
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.state = seed;
+    }
+
+    fn state(&self) -> u64 {
+        self.state
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.state = state;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut a = SplitMix64::new(42);
+        let mut b = SplitMix64::new(42);
+        let first: Vec<u8> = (0..32).map(|_| a.next_byte()).collect();
+        let second: Vec<u8> = (0..32).map(|_| b.next_byte()).collect();
+        assert_eq!(first, second);
+        let mut c = SplitMix64::new(43);
+        assert_ne!(first, (0..32).map(|_| c.next_byte()).collect::<Vec<u8>>());
+
+        // resuming from a saved state continues the sequence
+        let state = a.state();
+        let next = a.next_byte();
+        b.set_state(state);
+        assert_eq!(b.next_byte(), next);
+    }
+}
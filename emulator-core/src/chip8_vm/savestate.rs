@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// Save state layout, all integers little endian:
+///
+/// ```text
+/// magic    "C8ST"
+/// version  u16
+/// rom hash 20 bytes, SHA-1 of the ROM the state was taken from
+/// sections until the end: tag [u8; 4], length u32, `length` bytes of payload
+/// ```
+///
+/// New data goes in new sections or at the end of existing ones: readers skip
+/// unknown sections, ignore trailing bytes and leave state untouched when a
+/// section or trailing field is missing, so older states keep loading.
+/// `FORMAT_VERSION` only changes when a field's meaning does.
+pub const FORMAT_VERSION: u16 = 1;
+const MAGIC: &[u8; 4] = b"C8ST";
+pub const ROM_HASH_SIZE: usize = 20;
+
+pub type Tag = [u8; 4];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveStateError {
+    NotASaveState,
+    UnsupportedVersion(u16),
+    RomMismatch,
+    Truncated,
+    Invalid(&'static str),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::NotASaveState => write!(f, "not a save state"),
+            SaveStateError::UnsupportedVersion(version) => write!(
+                f,
+                "save state version {} is newer than the supported {}",
+                version, FORMAT_VERSION
+            ),
+            SaveStateError::RomMismatch => write!(f, "save state belongs to a different ROM"),
+            SaveStateError::Truncated => write!(f, "save state is truncated"),
+            SaveStateError::Invalid(field) => write!(f, "save state has an invalid {}", field),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+pub struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new(rom_hash: &[u8; ROM_HASH_SIZE]) -> Self {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(rom_hash);
+        StateWriter { bytes }
+    }
+
+    pub fn section(&mut self, tag: &Tag, payload: &[u8]) {
+        self.bytes.extend_from_slice(tag);
+        self.bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        self.bytes.extend_from_slice(payload);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+pub struct StateReader<'a> {
+    rom_hash: [u8; ROM_HASH_SIZE],
+    sections: HashMap<Tag, &'a [u8]>,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Result<Self, SaveStateError> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(SaveStateError::NotASaveState);
+        }
+        let mut header = Section::new(&bytes[MAGIC.len()..]);
+        let version = header.u16()?;
+        if version > FORMAT_VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+        let mut rom_hash = [0; ROM_HASH_SIZE];
+        rom_hash.copy_from_slice(header.bytes(ROM_HASH_SIZE)?);
+
+        let mut sections = HashMap::new();
+        while !header.is_empty() {
+            let mut tag = [0; 4];
+            tag.copy_from_slice(header.bytes(4)?);
+            let length = header.u32()? as usize;
+            sections.insert(tag, header.bytes(length)?);
+        }
+        Ok(StateReader { rom_hash, sections })
+    }
+
+    pub fn rom_hash(&self) -> &[u8; ROM_HASH_SIZE] {
+        &self.rom_hash
+    }
+
+    pub fn section(&self, tag: &Tag) -> Option<Section<'a>> {
+        self.sections.get(tag).map(|payload| Section::new(payload))
+    }
+}
+
+/// Cursor over a section payload.
+pub struct Section<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Section<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Section { bytes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn bytes(&mut self, count: usize) -> Result<&'a [u8], SaveStateError> {
+        if self.bytes.len() < count {
+            return Err(SaveStateError::Truncated);
+        }
+        let (head, tail) = self.bytes.split_at(count);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, SaveStateError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, SaveStateError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, SaveStateError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reader_skips_unknown_sections() {
+        let mut writer = StateWriter::new(&[7; ROM_HASH_SIZE]);
+        writer.section(b"NEW!", &[1, 2, 3]);
+        writer.section(b"TIMR", &[5, 6, 0xFF]);
+        let bytes = writer.finish();
+
+        let reader = StateReader::new(&bytes).unwrap();
+        assert_eq!(reader.rom_hash(), &[7; ROM_HASH_SIZE]);
+        let mut timers = reader.section(b"TIMR").unwrap();
+        assert_eq!(timers.u16().unwrap(), 0x0605);
+        assert!(reader.section(b"CPU ").is_none());
+
+        assert_eq!(
+            StateReader::new(&bytes[..bytes.len() - 1]).err(),
+            Some(SaveStateError::Truncated)
+        );
+        assert_eq!(StateReader::new(b"nope").err(), Some(SaveStateError::NotASaveState));
+    }
+}
@@ -1,39 +1,54 @@
-const STACK_SIZE: usize = 16;
+/// Nesting depth of the original COSMAC VIP interpreter.
+pub const VIP_STACK_DEPTH: usize = 12;
+/// Nesting depth of CHIP-48 and SUPER-CHIP.
+pub const SUPER_CHIP_STACK_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackError {
+    Overflow,
+    Underflow,
+}
 
 pub struct Stack {
-    stack: [u16; STACK_SIZE],
-    sp: u8,
+    stack: Vec<u16>,
+    depth: usize,
 }
 
 impl Stack {
-    pub fn new() -> Self {
+    pub fn new(depth: usize) -> Self {
         Stack {
-            stack: [0; STACK_SIZE],
-            sp: 0,
+            stack: Vec::with_capacity(depth),
+            depth,
         }
     }
 
-    pub fn pop(&mut self) -> u16 {
-        let index = self.sp as usize;
-        if self.sp > 0 {
-            self.sp -= 1;
+    pub fn push(&mut self, value: u16) -> Result<(), StackError> {
+        if self.stack.len() >= self.depth {
+            return Err(StackError::Overflow);
         }
-        self.stack[index]
+        self.stack.push(value);
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Result<u16, StackError> {
+        self.stack.pop().ok_or(StackError::Underflow)
     }
 
     pub fn reset(&mut self) {
-        self.sp = 0;
+        self.stack.clear();
     }
 
-    pub fn get(&self) -> u16 {
-        self.stack[self.sp as usize]
+    pub fn depth(&self) -> usize {
+        self.depth
     }
 
-    pub fn set(&mut self, value: u16) {
-        self.stack[self.sp as usize] = value;
+    /// Changes the maximum nesting depth, frames already on the stack are kept.
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
     }
 
-    pub fn increment_sp(&mut self) {
-        self.sp += 1;
+    /// Return addresses of the live calls, outermost first.
+    pub fn frames(&self) -> &[u16] {
+        &self.stack
     }
 }
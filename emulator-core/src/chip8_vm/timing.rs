@@ -0,0 +1,120 @@
+use super::instruction::Instruction;
+use super::vm::StepInfo;
+
+/// How a frame is paced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Timing {
+    /// A fixed number of instructions per frame, whatever they are.
+    #[default]
+    Instructions,
+    /// A budget of COSMAC VIP machine cycles per frame, charged per instruction,
+    /// so games run at the speed they did on the original hardware.
+    CosmacVip,
+}
+
+impl TryFrom<u8> for Timing {
+    type Error = u8;
+
+    fn try_from(id: u8) -> Result<Self, Self::Error> {
+        match id {
+            0 => Ok(Timing::Instructions),
+            1 => Ok(Timing::CosmacVip),
+            _ => Err(id),
+        }
+    }
+}
+
+/// The VIP's 1802 runs at 1.7609 MHz with 8 clocks per machine cycle.
+pub const VIP_CYCLES_PER_FRAME: u32 = 3668;
+/// Taken from every frame by the display interrupt: the DMA of 128 scanlines of
+/// 8 bytes plus the interrupt routine updating the timers.
+pub const VIP_INTERRUPT_CYCLES: u32 = 1024 + 46;
+/// Spent by the interpreter's fetch and decode loop before each instruction.
+const FETCH_CYCLES: u32 = 40;
+/// Charged for the SUPER-CHIP and XO-CHIP instructions the VIP never had.
+const NON_VIP_CYCLES: u32 = 10;
+
+/// Approximate machine cycles the VIP interpreter spends on `step`, after the
+/// interpreter's own disassembly. `next_pc` is where execution continued and `v`
+/// the registers afterwards, which skips, sprites and FX33 depend on.
+pub fn vip_cycles(step: &StepInfo, next_pc: u16, v: &[u8]) -> u32 {
+    let skip = if next_pc == step.pc.wrapping_add(4) { 4 } else { 0 };
+    let cycles = match step.instruction {
+        Instruction::ClearScreen => 3078,
+        Instruction::Return => 10,
+        Instruction::Jump { .. } => 12,
+        Instruction::Call { .. } => 26,
+        Instruction::SkipIfEqual { .. } | Instruction::SkipIfNotEqual { .. } => 10 + skip,
+        Instruction::SkipIfVxEqualVy { .. } | Instruction::SkipIfVxNotVy { .. } => 14 + skip,
+        Instruction::SetVx { .. } => 6,
+        Instruction::AddVx { .. } => 10,
+        Instruction::SetVxVy { .. }
+        | Instruction::SetVxOrVy { .. }
+        | Instruction::SetVxAndVy { .. }
+        | Instruction::SetVxXorVy { .. }
+        | Instruction::AddVxVy { .. }
+        | Instruction::SubVxVy { .. }
+        | Instruction::ShiftRight { .. }
+        | Instruction::SubVyVx { .. }
+        | Instruction::ShiftLeft { .. } => 44,
+        Instruction::SetI { .. } => 12,
+        Instruction::JumpV0 { .. } => 22,
+        Instruction::Random { .. } => 36,
+        Instruction::Draw { x, n, .. } => draw_cycles(v[x as usize], n),
+        Instruction::SkipIfPressed { .. } | Instruction::SkipIfNotPressed { .. } => 14 + skip,
+        Instruction::SetVxToDelayTimer { .. }
+        | Instruction::SetDelayTimer { .. }
+        | Instruction::SetSoundTimer { .. } => 10,
+        // charged on every poll while no key is pressed
+        Instruction::WaitForKeyPress { .. } => 16,
+        Instruction::AddVxToI { .. } | Instruction::SetIToSprite { .. } => 16,
+        Instruction::StoreBCD { x } => {
+            // the digits are found by repeated subtraction
+            let value = v[x as usize] as u32;
+            80 + 16 * (value / 100 + value / 10 % 10 + value % 10)
+        }
+        Instruction::StoreRegisters { x } | Instruction::LoadRegisters { x } => {
+            14 + 14 * (x as u32 + 1)
+        }
+        _ => NON_VIP_CYCLES,
+    };
+    FETCH_CYCLES + cycles
+}
+
+/// Rows are shifted into place one bit at a time, so sprites away from a byte
+/// boundary take longer.
+fn draw_cycles(x: u8, rows: u8) -> u32 {
+    let shift = (x % 8) as u32;
+    26 + rows as u32 * (46 + 8 * shift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8_vm::instruction::decode;
+
+    fn cycles(opcode: u16, next_pc: u16, v: &[u8]) -> u32 {
+        let step = StepInfo {
+            pc: 0x200,
+            opcode,
+            instruction: decode(opcode),
+        };
+        vip_cycles(&step, next_pc, v)
+    }
+
+    #[test]
+    fn test_costs_depend_on_operands() {
+        let mut v = [0; 16];
+        assert_eq!(cycles(0x3000, 0x202, &v), 50);
+        assert_eq!(cycles(0x3000, 0x204, &v), 54);
+
+        v[1] = 8;
+        let aligned = cycles(0xD125, 0x202, &v);
+        v[1] = 9;
+        assert!(cycles(0xD125, 0x202, &v) > aligned);
+
+        v[2] = 199;
+        assert!(cycles(0xF233, 0x202, &v) > cycles(0xF133, 0x202, &v));
+        assert!(cycles(0xFF55, 0x202, &v) > cycles(0xF055, 0x202, &v));
+    }
+}
@@ -1,17 +1,28 @@
-use core::panic;
-
-use super::display::{Display, SCREEN_HEIGHT, SCREEN_WIDTH};
+use super::display::{Display, DisplayState, HIRES_SCREEN_WIDTH};
+use super::error::VmError;
+use super::instruction::{decode, Instruction};
 use super::keyboard::Keypad;
-use super::stack::Stack;
-use crate::resources::FONTSET;
+use super::quirks::{Platform, Quirks, NUM_QUIRKS};
+use super::rng::{RandomSource, SplitMix64};
+use super::savestate::{SaveStateError, StateReader, StateWriter, ROM_HASH_SIZE};
+use super::stack::{Stack, SUPER_CHIP_STACK_DEPTH, VIP_STACK_DEPTH};
+use crate::resources::{BIG_FONTSET, BIG_FONTSET_SIZE, FONTSET};
+use crate::RomError;
 
 
 use crate::chip8_vm::timers::Timers;
 
 const MEMORY_SIZE: usize = 4096;
+const XO_CHIP_MEMORY_SIZE: usize = 65536;
+const AUDIO_PATTERN_SIZE: usize = 16;
+const DEFAULT_PITCH: u8 = 64;
 const FONTSET_SIZE: usize = 80;
+const BIG_FONTSET_ADDRESS: usize = FONTSET_SIZE;
+/// Both fonts are loaded from address 0, the small one first.
+pub const FONT_AREA_SIZE: usize = BIG_FONTSET_ADDRESS + BIG_FONTSET_SIZE;
 const NUM_REGISTERS: usize = 16;
-const PROGRAM_START_ADDRESS: usize = 0x200;
+const NUM_RPL_FLAGS: usize = 16;
+pub const PROGRAM_START_ADDRESS: usize = 0x200;
 
 struct Registers {
     v: [u8; NUM_REGISTERS], // general purpose registers
@@ -20,7 +31,7 @@ struct Registers {
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
-enum CpuState {
+pub enum CpuState {
     Running,
     Halted,
 }
@@ -33,62 +44,68 @@ enum ProgramCounterState {
     Unknown,
 }
 
+/// What a single successful `Chip8VM::cycle` executed.
 #[derive(Debug, PartialEq, Copy, Clone)]
-pub enum Instructions {
-    ClearScreen = 0x0000,
-    Return = 0x000E,
-    Jump = 0x1000,
-    Call = 0x2000,
-    SkipIfEqual = 0x3000,
-    SkipIfNotEqual = 0x4000,
-    SkipIfVxEqualVy = 0x5000,
-    SetVx = 0x6000,
-    AddVx = 0x7000,
-    SetVxVy = 0x8000,
-    SetVxOrVy = 0x8001,
-    SetVxAndVy = 0x8002,
-    SetVxXorVy = 0x8003,
-    AddVxVy = 0x8004,
-    SubVxVy = 0x8005,
-    ShiftRight = 0x8006,
-    SubVyVx = 0x8007,
-    ShiftLeft = 0x800E,
-    SkipIfVxNotVy = 0x9000,
-    SetI = 0xA000,
-    JumpV0 = 0xB000,
-    Random = 0xC000,
-    Draw = 0xD000,
-    SkipIfPressed = 0xE09E,
-    SkipIfNotPressed = 0xE0A1,
-    SetVxToDelayTimer = 0xF007,
-    WaitForKeyPress = 0xF00A,
-    SetDelayTimer = 0xF015,
-    SetSoundTimer = 0xF018,
-    AddVxToI = 0xF01E,
-    SetIToSprite = 0xF029,
-    StoreBCD = 0xF033,
-    StoreRegisters = 0xF055,
-    LoadRegisters = 0xF065,
-    Unknown = 0xFFFF,
+pub struct StepInfo {
+    pub pc: u16,
+    pub opcode: u16,
+    pub instruction: Instruction,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// A byte of RAM read or written by an instruction, fetching opcodes aside.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct MemoryAccess {
+    pub address: usize,
+    pub kind: AccessKind,
+}
+
+/// A sprite drawn by DXYN: where it was read from and where it went on screen.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct SpriteDraw {
+    pub address: u16,
+    pub x: u8,
+    pub y: u8,
+    pub width: u8,
+    pub height: u8,
 }
 
 pub struct Chip8VM {
-    ram: [u8; MEMORY_SIZE],
+    ram: Vec<u8>,
     stack: Stack,
     registers: Registers,
     pub keypad: Keypad,
     pub timers: Timers,
     pub display: Display,
+    rpl_flags: [u8; NUM_RPL_FLAGS],
+    audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    pitch: u8,
+    platform: Platform,
+    quirks: Quirks,
+    vblank: bool,
     state: CpuState,
+    fault: Option<VmError>,
     program_counter_state: ProgramCounterState,
-    pub current_instruction: Instructions,
+    pub current_instruction: Instruction,
+    rng: Box<dyn RandomSource>,
+    /// Reapplied on every reset so reloading a ROM replays the same numbers.
+    seed: u64,
+    /// Accesses made by the last instruction, when logging is on.
+    access_log: Option<Vec<MemoryAccess>>,
+    last_sprite: Option<SpriteDraw>,
 }
 
 impl Chip8VM {
-    pub fn new() -> Self {
+    pub fn new(quirks: Quirks) -> Self {
+        let seed = rand::random::<u64>();
         Chip8VM {
-            ram: [0; MEMORY_SIZE],
-            stack: Stack::new(),
+            ram: vec![0; MEMORY_SIZE],
+            stack: Stack::new(VIP_STACK_DEPTH),
             registers: Registers {
                 v: [0; NUM_REGISTERS],
                 i: 0,
@@ -97,13 +114,24 @@ impl Chip8VM {
             timers: Timers::new(),
             keypad: Keypad::new(),
             display: Display::new(),
+            rpl_flags: [0; NUM_RPL_FLAGS],
+            audio_pattern: [0; AUDIO_PATTERN_SIZE],
+            pitch: DEFAULT_PITCH,
+            platform: Platform::Chip8,
+            quirks,
+            vblank: false,
             state: CpuState::Halted,
+            fault: None,
             program_counter_state: ProgramCounterState::Unknown,
-            current_instruction: Instructions::Unknown,
+            current_instruction: Instruction::Unknown { opcode: 0 },
+            rng: Box::new(SplitMix64::new(seed)),
+            seed,
+            access_log: None,
+            last_sprite: None,
         }
     }
     pub fn reset(&mut self) {
-        self.ram = [0; MEMORY_SIZE];
+        self.ram = vec![0; self.memory_size()];
         self.stack.reset();
         self.registers = Registers {
             v: [0; NUM_REGISTERS],
@@ -112,310 +140,802 @@ impl Chip8VM {
         };
         self.timers.reset();
         self.keypad.reset();
-        self.display.clear();
+        self.display.set_hires(false);
+        self.display.set_plane_mask(1);
+        self.audio_pattern = [0; AUDIO_PATTERN_SIZE];
+        self.pitch = DEFAULT_PITCH;
+        self.vblank = false;
         self.state = CpuState::Halted;
+        self.fault = None;
         self.program_counter_state = ProgramCounterState::Next;
-        self.current_instruction = Instructions::Unknown;
+        self.current_instruction = Instruction::Unknown { opcode: 0 };
+        self.rng.seed(self.seed);
+        self.last_sprite = None;
+    }
+    pub fn get_state(&self) -> CpuState {
+        self.state
+    }
+
+    /// The error that halted the VM, if any.
+    pub fn get_fault(&self) -> Option<VmError> {
+        self.fault
+    }
+
+    pub fn get_pc(&self) -> u16 {
+        self.registers.pc
+    }
+
+    pub fn get_i(&self) -> u16 {
+        self.registers.i
+    }
+
+    pub fn get_v(&self) -> [u8; NUM_REGISTERS] {
+        self.registers.v
+    }
+
+    pub fn get_ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// For debuggers, which may patch code and data as well as registers.
+    pub fn get_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.registers.pc = pc;
+    }
+
+    pub fn set_i(&mut self, i: u16) {
+        self.registers.i = i;
+    }
+
+    pub fn set_v(&mut self, x: usize, value: u8) {
+        self.registers.v[x] = value;
+    }
+
+    pub fn get_platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// Selects which instruction set extensions are decoded, along with the
+    /// platform's memory size and stack depth.
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+        self.ram.resize(self.memory_size(), 0);
+        self.stack.set_depth(match platform {
+            Platform::Chip8 => VIP_STACK_DEPTH,
+            _ => SUPER_CHIP_STACK_DEPTH,
+        });
+    }
+
+    pub fn get_stack_depth(&self) -> usize {
+        self.stack.depth()
+    }
+
+    /// Overrides the platform's stack depth, e.g. for homebrew that nests deeper.
+    pub fn set_stack_depth(&mut self, depth: usize) {
+        self.stack.set_depth(depth);
+    }
+
+    /// Return addresses of the calls in progress, outermost first.
+    pub fn get_call_stack(&self) -> &[u16] {
+        self.stack.frames()
+    }
+
+    /// XO-CHIP programs can address 64K, every other platform 4K.
+    pub fn memory_size(&self) -> usize {
+        Self::memory_size_for(self.platform)
+    }
+
+    pub fn memory_size_for(platform: Platform) -> usize {
+        match platform {
+            Platform::XoChip => XO_CHIP_MEMORY_SIZE,
+            _ => MEMORY_SIZE,
+        }
+    }
+
+    /// The XO-CHIP 128-bit audio pattern, played one bit per sample.
+    pub fn get_audio_pattern(&self) -> [u8; AUDIO_PATTERN_SIZE] {
+        self.audio_pattern
+    }
+
+    pub fn get_pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    /// Number of audio pattern bits played per second at the current pitch.
+    pub fn get_playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    /// Replaces the random number source, seeding it with the current seed.
+    pub fn set_random_source(&mut self, mut rng: Box<dyn RandomSource>) {
+        rng.seed(self.seed);
+        self.rng = rng;
+    }
+
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Restarts the random sequence from `seed`, also used by later resets.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng.seed(seed);
+    }
+
+    pub fn get_rng_state(&self) -> u64 {
+        self.rng.state()
+    }
+
+    pub fn set_rng_state(&mut self, state: u64) {
+        self.rng.set_state(state);
+    }
+
+    pub fn get_quirks(&self) -> Quirks {
+        self.quirks
     }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Records the memory accesses of each instruction for `get_last_accesses`.
+    pub fn set_access_logging(&mut self, enabled: bool) {
+        self.access_log = enabled.then(Vec::new);
+    }
+
+    /// RAM read and written by the last instruction, empty unless logging is on.
+    pub fn get_last_accesses(&self) -> &[MemoryAccess] {
+        self.access_log.as_deref().unwrap_or_default()
+    }
+
+    /// The sprite the last DXYN drew, for graphics viewers.
+    pub fn get_last_sprite(&self) -> Option<SpriteDraw> {
+        self.last_sprite
+    }
+
+    /// Signals the start of a new frame to instructions waiting on the display.
+    pub fn signal_vblank(&mut self) {
+        self.vblank = true;
+    }
+
     pub fn init_fontset(&mut self) {
-        for i in 0..FONTSET_SIZE {
-            self.ram[i] = FONTSET[i];
+        self.ram[..FONTSET_SIZE].copy_from_slice(&FONTSET);
+        self.ram[BIG_FONTSET_ADDRESS..BIG_FONTSET_ADDRESS + BIG_FONTSET_SIZE]
+            .copy_from_slice(&BIG_FONTSET);
+    }
+
+    /// Bytes available for a program between the start address and the end of memory.
+    pub fn program_space(&self) -> usize {
+        Self::program_space_for(self.platform)
+    }
+
+    /// Bytes available to a ROM on `platform`.
+    pub fn program_space_for(platform: Platform) -> usize {
+        Self::memory_size_for(platform) - PROGRAM_START_ADDRESS
+    }
+
+    pub fn load_rom(&mut self, rom: Vec<u8>) -> Result<(), RomError> {
+        if rom.len() > self.program_space() {
+            return Err(RomError::TooLarge {
+                size: rom.len(),
+                max_size: self.program_space(),
+            });
+        }
+        self.ram[PROGRAM_START_ADDRESS..PROGRAM_START_ADDRESS + rom.len()].copy_from_slice(&rom);
+        Ok(())
+    }
+
+    /// Writes every section of the machine state, see `savestate` for the format.
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        let mut platform = vec![self.platform as u8];
+        platform.extend_from_slice(&(self.stack.depth() as u16).to_le_bytes());
+        platform.extend_from_slice(&self.quirks.to_bytes());
+        writer.section(b"PLAT", &platform);
+
+        let mut cpu = self.registers.v.to_vec();
+        cpu.extend_from_slice(&self.registers.i.to_le_bytes());
+        cpu.extend_from_slice(&self.registers.pc.to_le_bytes());
+        cpu.push((self.state == CpuState::Halted) as u8);
+        cpu.push(self.vblank as u8);
+        writer.section(b"CPU ", &cpu);
+
+        let mut stack = (self.stack.frames().len() as u16).to_le_bytes().to_vec();
+        for frame in self.stack.frames() {
+            stack.extend_from_slice(&frame.to_le_bytes());
+        }
+        writer.section(b"STCK", &stack);
+
+        writer.section(b"RAM ", &self.ram);
+        writer.section(
+            b"TIMR",
+            &[self.timers.get_delay_timer(), self.timers.get_sound_timer()],
+        );
+        let keys: Vec<u8> = (0..self.keypad.len() as u8).map(|key| self.keypad.get_key(key)).collect();
+        writer.section(b"KEYS", &keys);
+
+        let mut display = vec![
+            self.display.is_hires() as u8,
+            self.display.plane_mask(),
+            self.display.state as u8,
+        ];
+        for row in self.display.buffer.iter() {
+            display.extend_from_slice(row);
+        }
+        writer.section(b"DISP", &display);
+
+        let mut xo_chip = self.audio_pattern.to_vec();
+        xo_chip.push(self.pitch);
+        writer.section(b"XOCH", &xo_chip);
+        writer.section(b"FLAG", &self.rpl_flags);
+
+        let mut rng = self.seed.to_le_bytes().to_vec();
+        rng.extend_from_slice(&self.rng.state().to_le_bytes());
+        writer.section(b"RNG ", &rng);
+    }
+
+    /// Restores a state written by `save_state`. Sections missing from older
+    /// states leave that part of the machine as it is; on error nothing changes.
+    pub fn load_state(&mut self, reader: &StateReader) -> Result<(), SaveStateError> {
+        let mut backup = StateWriter::new(&[0; ROM_HASH_SIZE]);
+        self.save_state(&mut backup);
+        let backup = backup.finish();
+
+        let result = self.apply_state(reader);
+        if result.is_err() {
+            let reader = StateReader::new(&backup).expect("backup state is valid");
+            self.apply_state(&reader).expect("backup state is valid");
         }
+        result
     }
 
-    pub fn load_rom(&mut self, rom: Vec<u8>) {
-        for (i, byte) in rom.iter().enumerate() {
-            self.ram[i + PROGRAM_START_ADDRESS] = *byte
+    fn apply_state(&mut self, reader: &StateReader) -> Result<(), SaveStateError> {
+        if let Some(mut section) = reader.section(b"PLAT") {
+            let platform = Platform::try_from(section.u8()?)
+                .map_err(|_| SaveStateError::Invalid("platform"))?;
+            self.set_platform(platform);
+            self.stack.set_depth(section.u16()? as usize);
+            let quirks = section.bytes(NUM_QUIRKS)?;
+            self.quirks = Quirks::from_bytes(quirks).ok_or(SaveStateError::Truncated)?;
+        }
+
+        if let Some(mut section) = reader.section(b"CPU ") {
+            self.registers.v.copy_from_slice(section.bytes(NUM_REGISTERS)?);
+            self.registers.i = section.u16()?;
+            self.registers.pc = section.u16()?;
+            self.state = match section.u8()? {
+                0 => CpuState::Running,
+                _ => CpuState::Halted,
+            };
+            self.vblank = section.u8()? != 0;
+        }
+
+        if let Some(mut section) = reader.section(b"STCK") {
+            self.stack.reset();
+            for _ in 0..section.u16()? {
+                let frame = section.u16()?;
+                self.stack
+                    .push(frame)
+                    .map_err(|_| SaveStateError::Invalid("call stack"))?;
+            }
+        }
+
+        if let Some(mut section) = reader.section(b"RAM ") {
+            let ram = section.bytes(self.memory_size())?;
+            self.ram.copy_from_slice(ram);
+        }
+
+        if let Some(mut section) = reader.section(b"TIMR") {
+            self.timers.set_delay_timer(section.u8()?);
+            self.timers.set_sound_timer(section.u8()?);
         }
+
+        if let Some(mut section) = reader.section(b"KEYS") {
+            for key in 0..self.keypad.len() as u8 {
+                self.keypad.set_key(key, section.u8()?);
+            }
+        }
+
+        if let Some(mut section) = reader.section(b"DISP") {
+            self.display.set_hires(section.u8()? != 0);
+            self.display.set_plane_mask(section.u8()?);
+            let state = match section.u8()? {
+                0 => DisplayState::Draw,
+                1 => DisplayState::Clear,
+                _ => DisplayState::Noop,
+            };
+            for row in self.display.buffer.iter_mut() {
+                row.copy_from_slice(section.bytes(HIRES_SCREEN_WIDTH)?);
+            }
+            self.display.state = state;
+        }
+
+        if let Some(mut section) = reader.section(b"XOCH") {
+            self.audio_pattern
+                .copy_from_slice(section.bytes(AUDIO_PATTERN_SIZE)?);
+            self.pitch = section.u8()?;
+        }
+
+        if let Some(mut section) = reader.section(b"FLAG") {
+            self.rpl_flags.copy_from_slice(section.bytes(NUM_RPL_FLAGS)?);
+        }
+
+        if let Some(mut section) = reader.section(b"RNG ") {
+            self.seed = section.u64()?;
+            self.rng.set_state(section.u64()?);
+        }
+
+        self.fault = None;
+        Ok(())
     }
 
-    pub fn cycle(&mut self) {
+    /// Executes one instruction. On error the VM halts and keeps returning
+    /// the same error until it is reset.
+    pub fn cycle(&mut self) -> Result<StepInfo, VmError> {
+        if let Some(error) = self.fault {
+            return Err(error);
+        }
         self.state = CpuState::Running;
-        let opcode = self.fetch();
-        if self.execute(opcode) == CpuState::Halted {
-            panic!("Unknown opcode: {:X?}", opcode);
+        if let Some(log) = &mut self.access_log {
+            log.clear();
         }
+        let pc = self.registers.pc;
+        let result = self.fetch().and_then(|opcode| self.execute(opcode).map(|_| opcode));
+        let opcode = match result {
+            Ok(opcode) => opcode,
+            Err(error) => {
+                self.state = CpuState::Halted;
+                self.fault = Some(error);
+                return Err(error);
+            }
+        };
         match self.program_counter_state {
             ProgramCounterState::Next => self.next_instruction(),
             ProgramCounterState::Skip => self.skip_next_instruction(),
             ProgramCounterState::Jump(addr) => self.jump_next_instruction(addr),
             _ => (),
         }
+        Ok(StepInfo {
+            pc,
+            opcode,
+            instruction: self.current_instruction,
+        })
     }
 
     fn next_instruction(&mut self) {
-        self.registers.pc += 2;
+        self.registers.pc = self.registers.pc.wrapping_add(2);
     }
 
     fn skip_next_instruction(&mut self) {
-        self.registers.pc += 4;
+        // F000 NNNN is the only four byte long instruction
+        let next_pc = self.registers.pc as usize + 2;
+        if self.has_xo_chip_instructions() && self.read_word(next_pc) == Ok(0xF000) {
+            self.registers.pc = self.registers.pc.wrapping_add(6);
+        } else {
+            self.registers.pc = self.registers.pc.wrapping_add(4);
+        }
     }
 
     fn jump_next_instruction(&mut self, addr: u16) {
         self.registers.pc = addr;
     }
 
-    fn fetch(&mut self) -> u16 {
-        let pc = self.registers.pc as usize;
-        let hi = self.ram[pc] as u16;
-        let lo = self.ram[pc + 1] as u16;
+    fn fetch(&mut self) -> Result<u16, VmError> {
+        let pc = self.registers.pc;
+        self.read_word(pc as usize).map_err(|_| VmError::PcOutOfBounds { pc })
+    }
+
+    /// Reads opcodes and their operands, which aren't logged as data accesses.
+    fn read_word(&self, address: usize) -> Result<u16, VmError> {
+        let hi = self.peek_memory(address)? as u16;
+        let lo = self.peek_memory(address + 1)? as u16;
 
-        (hi<< 8) | lo
+        Ok((hi<< 8) | lo)
     }
 
-    fn execute(&mut self, opcode: u16) -> CpuState {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
-        let nn = (opcode & 0x00FF) as u16;
-        let n = (opcode & 0x000F) as u16;
-        let jump_addr = opcode & 0x0FFF;
+    fn peek_memory(&self, address: usize) -> Result<u8, VmError> {
+        self.ram.get(address).copied().ok_or(VmError::MemoryOutOfBounds {
+            pc: self.registers.pc,
+            addr: address,
+        })
+    }
+
+    fn read_memory(&mut self, address: usize) -> Result<u8, VmError> {
+        let value = self.peek_memory(address)?;
+        self.log_access(address, AccessKind::Read);
+        Ok(value)
+    }
+
+    fn log_access(&mut self, address: usize, kind: AccessKind) {
+        if let Some(log) = &mut self.access_log {
+            log.push(MemoryAccess { address, kind });
+        }
+    }
+
+    fn write_memory(&mut self, address: usize, value: u8) -> Result<(), VmError> {
+        let pc = self.registers.pc;
+        let byte = self
+            .ram
+            .get_mut(address)
+            .ok_or(VmError::MemoryOutOfBounds { pc, addr: address })?;
+        *byte = value;
+        self.log_access(address, AccessKind::Write);
+        Ok(())
+    }
+
+    fn has_super_chip_instructions(&self) -> bool {
+        matches!(self.platform, Platform::SuperChip | Platform::XoChip)
+    }
+
+    fn has_xo_chip_instructions(&self) -> bool {
+        self.platform == Platform::XoChip
+    }
+
+    /// Registers from Vx to Vy in the order 5XY2/5XY3 store them, descending if x > y.
+    fn register_range(x: usize, y: usize) -> Vec<usize> {
+        if x <= y {
+            (x..=y).collect()
+        } else {
+            (y..=x).rev().collect()
+        }
+    }
+
+    /// SUPER-CHIP only has 8 RPL flags, XO-CHIP extends them to all 16 registers.
+    fn rpl_flag_count(&self, x: usize) -> usize {
+        match self.platform {
+            Platform::XoChip => x + 1,
+            _ => x.min(7) + 1,
+        }
+    }
+
+    fn supports(&self, instruction: Instruction) -> bool {
+        match instruction.extension() {
+            Some(Platform::XoChip) => self.has_xo_chip_instructions(),
+            Some(_) => self.has_super_chip_instructions(),
+            None => true,
+        }
+    }
+
+    fn execute(&mut self, opcode: u16) -> Result<(), VmError> {
+        let pc = self.registers.pc;
+        let instruction = decode(opcode);
+        self.current_instruction = instruction;
         self.program_counter_state = ProgramCounterState::Next;
+        if !self.supports(instruction) {
+            return Err(VmError::UnknownOpcode { pc, opcode });
+        }
 
-        match opcode & 0xF000 {
-            0x0000 => match opcode & 0x000F {
-                0x0000 => {
-                    self.display.clear();
-                    self.current_instruction = Instructions::Draw;
-                }
-                0x000E => {
-                    self.program_counter_state = ProgramCounterState::Jump(self.stack.pop());
-                    self.current_instruction = Instructions::Return;
-                }
-                _ => {
-                    self.state = CpuState::Halted;
-                    self.current_instruction = Instructions::Unknown;
-                }
-            },
-            0x1000 => {
-                self.program_counter_state = ProgramCounterState::Jump(jump_addr);
-                self.current_instruction = Instructions::Jump;
+        match instruction {
+            Instruction::ClearScreen => self.display.clear_planes(),
+            Instruction::Return => {
+                let address = self
+                    .stack
+                    .pop()
+                    .map_err(|_| VmError::StackUnderflow { pc })?;
+                self.program_counter_state = ProgramCounterState::Jump(address);
             }
-            0x2000 => {
-                if self.stack.get() != 0 {
-                    self.stack.increment_sp();
-                }
-                self.stack.set(self.registers.pc + 2);
-                self.program_counter_state = ProgramCounterState::Jump(jump_addr);
-                self.current_instruction = Instructions::Call;
+            Instruction::ScrollDown { n } => self.display.scroll_down(n as usize),
+            Instruction::ScrollUp { n } => self.display.scroll_up(n as usize),
+            Instruction::ScrollRight => self.display.scroll_right(4),
+            Instruction::ScrollLeft => self.display.scroll_left(4),
+            Instruction::Exit => {
+                // the program has exited, stay on this instruction
+                self.program_counter_state = ProgramCounterState::Jump(pc);
+            }
+            Instruction::LowResolution => self.display.set_hires(false),
+            Instruction::HighResolution => self.display.set_hires(true),
+            Instruction::Jump { nnn } => {
+                self.program_counter_state = ProgramCounterState::Jump(nnn);
+            }
+            Instruction::Call { nnn } => {
+                self.stack
+                    .push(pc.wrapping_add(2))
+                    .map_err(|_| VmError::StackOverflow { pc })?;
+                self.program_counter_state = ProgramCounterState::Jump(nnn);
             }
-            0x3000 => {
-                if self.registers.v[x] == nn as u8 {
+            Instruction::SkipIfEqual { x, nn } => {
+                if self.registers.v[x as usize] == nn {
                     self.program_counter_state = ProgramCounterState::Skip;
                 }
-                self.current_instruction = Instructions::SkipIfEqual;
             }
-            0x4000 => {
-                if self.registers.v[x] != nn as u8 {
+            Instruction::SkipIfNotEqual { x, nn } => {
+                if self.registers.v[x as usize] != nn {
                     self.program_counter_state = ProgramCounterState::Skip;
                 }
-                self.current_instruction = Instructions::SkipIfNotEqual;
             }
-            0x5000 => {
-                if self.registers.v[x] == self.registers.v[y] {
+            Instruction::SkipIfVxEqualVy { x, y } => {
+                if self.registers.v[x as usize] == self.registers.v[y as usize] {
                     self.program_counter_state = ProgramCounterState::Skip;
                 }
-                self.current_instruction = Instructions::SkipIfVxEqualVy;
             }
-            0x6000 => {
-                self.registers.v[x] = nn as u8;
-                self.current_instruction = Instructions::SetVx;
-            }
-            0x7000 => {
-                self.registers.v[x] = self.registers.v[x].wrapping_add(nn as u8);
-                self.current_instruction = Instructions::AddVx;
-            }
-            0x8000 => match opcode & 0x000F {
-                0x0000 => {
-                    self.registers.v[x] = self.registers.v[y];
-                    self.current_instruction = Instructions::SetVxVy;
-                }
-                0x0001 => {
-                    self.registers.v[x] |= self.registers.v[y];
-                    self.current_instruction = Instructions::SetVxOrVy;
-                }
-                0x0002 => {
-                    self.registers.v[x] &= self.registers.v[y];
-                    self.current_instruction = Instructions::SetVxAndVy;
-                }
-                0x0003 => {
-                    self.registers.v[x] ^= self.registers.v[y];
-                    self.current_instruction = Instructions::SetVxXorVy;
+            Instruction::SaveRange { x, y } => {
+                let address = self.registers.i as usize;
+                let registers = Self::register_range(x as usize, y as usize);
+                for (offset, register) in registers.into_iter().enumerate() {
+                    self.write_memory(address + offset, self.registers.v[register])?;
                 }
-                0x0004 => {
-                    let vy = self.registers.v[x] as u16 + self.registers.v[y] as u16;
-                    self.registers.v[0xF] = (vy > 0xFF) as u8;
-                    self.registers.v[x] = vy as u8;
-                    self.current_instruction = Instructions::AddVxVy;
+            }
+            Instruction::LoadRange { x, y } => {
+                let address = self.registers.i as usize;
+                let registers = Self::register_range(x as usize, y as usize);
+                for (offset, register) in registers.into_iter().enumerate() {
+                    self.registers.v[register] = self.read_memory(address + offset)?;
                 }
-                0x0005 => {
-                    self.registers.v[0xF] = (self.registers.v[x] > self.registers.v[y]) as u8;
-                    self.registers.v[x] = self.registers.v[x].wrapping_sub(self.registers.v[y]);
-                    self.current_instruction = Instructions::SubVxVy;
+            }
+            Instruction::SetVx { x, nn } => self.registers.v[x as usize] = nn,
+            Instruction::AddVx { x, nn } => {
+                self.registers.v[x as usize] = self.registers.v[x as usize].wrapping_add(nn);
+            }
+            Instruction::SetVxVy { x, y } => {
+                self.registers.v[x as usize] = self.registers.v[y as usize];
+            }
+            Instruction::SetVxOrVy { x, y } => {
+                self.registers.v[x as usize] |= self.registers.v[y as usize];
+                if self.quirks.logic_resets_vf {
+                    self.registers.v[0xF] = 0;
                 }
-                0x0006 => {
-                    self.registers.v[0xF] = (self.registers.v[x] % 2 == 1) as u8;
-                    self.registers.v[x] = self.registers.v[x] >> 1;
-                    self.current_instruction = Instructions::ShiftRight;
+            }
+            Instruction::SetVxAndVy { x, y } => {
+                self.registers.v[x as usize] &= self.registers.v[y as usize];
+                if self.quirks.logic_resets_vf {
+                    self.registers.v[0xF] = 0;
                 }
-                0x0007 => {
-                    self.registers.v[0xF] = (self.registers.v[y] > self.registers.v[x]) as u8;
-                    self.registers.v[x] = self.registers.v[y].wrapping_sub(self.registers.v[x]);
-                    self.current_instruction = Instructions::SubVyVx;
+            }
+            Instruction::SetVxXorVy { x, y } => {
+                self.registers.v[x as usize] ^= self.registers.v[y as usize];
+                if self.quirks.logic_resets_vf {
+                    self.registers.v[0xF] = 0;
                 }
-                0x000E => {
-                    self.registers.v[0xF] = !(self.registers.v[x] < 0x80) as u8;
-                    self.registers.v[x] <<= 1;
-                    self.current_instruction = Instructions::ShiftLeft;
+            }
+            Instruction::AddVxVy { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                let (sum, carry) = self.registers.v[x].overflowing_add(self.registers.v[y]);
+                // the flag is written last so it wins when x is F
+                self.registers.v[x] = sum;
+                self.registers.v[0xF] = carry as u8;
+            }
+            Instruction::SubVxVy { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                let (difference, borrow) =
+                    self.registers.v[x].overflowing_sub(self.registers.v[y]);
+                self.registers.v[x] = difference;
+                self.registers.v[0xF] = !borrow as u8;
+            }
+            Instruction::ShiftRight { x, y } => {
+                let value = if self.quirks.shift_uses_vy {
+                    self.registers.v[y as usize]
+                } else {
+                    self.registers.v[x as usize]
+                };
+                self.registers.v[x as usize] = value >> 1;
+                self.registers.v[0xF] = value & 0x1;
+            }
+            Instruction::SubVyVx { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                let (difference, borrow) =
+                    self.registers.v[y].overflowing_sub(self.registers.v[x]);
+                self.registers.v[x] = difference;
+                self.registers.v[0xF] = !borrow as u8;
+            }
+            Instruction::ShiftLeft { x, y } => {
+                let value = if self.quirks.shift_uses_vy {
+                    self.registers.v[y as usize]
+                } else {
+                    self.registers.v[x as usize]
+                };
+                self.registers.v[x as usize] = value << 1;
+                self.registers.v[0xF] = value >> 7;
+            }
+            Instruction::SkipIfVxNotVy { x, y } => {
+                if self.registers.v[x as usize] != self.registers.v[y as usize] {
+                    self.program_counter_state = ProgramCounterState::Skip;
                 }
-                _ => {
-                    self.state = CpuState::Halted;
-                    self.current_instruction = Instructions::Unknown;
-                    panic!("Unknown opcode: {:X?}", opcode);
+            }
+            Instruction::SetI { nnn } => self.registers.i = nnn,
+            Instruction::JumpV0 { x, nnn } => {
+                let offset = if self.quirks.jump_uses_vx {
+                    self.registers.v[x as usize]
+                } else {
+                    self.registers.v[0]
+                };
+                self.program_counter_state = ProgramCounterState::Jump(nnn + offset as u16);
+            }
+            Instruction::Random { x, nn } => {
+                self.registers.v[x as usize] = self.rng.next_byte() & nn;
+            }
+            Instruction::Draw { x, y, n } => {
+                if self.quirks.display_wait && !self.vblank {
+                    // stall on this instruction until the next frame starts
+                    self.program_counter_state = ProgramCounterState::Jump(pc);
+                    return Ok(());
                 }
-            },
-            0x9000 => {
-                if self.registers.v[x] != self.registers.v[y] {
+                self.vblank = false;
+                self.draw_sprite(x as usize, y as usize, n as usize)?;
+            }
+            Instruction::SkipIfPressed { x } => {
+                // only the low nibble selects a key, as on the VIP
+                if self.keypad.get_key(self.registers.v[x as usize] & 0xF) != 0 {
                     self.program_counter_state = ProgramCounterState::Skip;
                 }
-                self.current_instruction = Instructions::SkipIfVxNotVy;
-            }
-            0xA000 => {
-                self.registers.i = jump_addr;
-                self.current_instruction = Instructions::SetI;
-            }
-            0xB000 => {
-                self.program_counter_state =
-                    ProgramCounterState::Jump(jump_addr + self.registers.v[0] as u16 );
-                self.current_instruction = Instructions::JumpV0;
-            }
-            0xC000 => {
-                self.registers.v[x] = (rand::random::<u8>()) & (opcode & 0x00FF) as u8;
-                self.current_instruction = Instructions::Random;
-            }
-
-            0xD000 => {
-                self.registers.v[0xF] = 0;
-                self.current_instruction = Instructions::Draw;
-                
-                for yline in 0..n {
-                    let pixel = self.ram[(self.registers.i + yline as u16) as usize];
-                    for xline in 0..8 {
-                        if (pixel & (0x80 >> xline)) != 0 {
-                            let x_coord = (self.registers.v[x] as usize + xline) % SCREEN_WIDTH;
-                            let y_coord =
-                                (self.registers.v[y] as usize + yline as usize) % SCREEN_HEIGHT;
-                            if self.display.buffer[y_coord][x_coord] == 1 {
-                                // collision detected
-                                self.registers.v[0xF] = 1;
-                            }
-                            self.display.draw_pixel(x_coord, y_coord);
-                        }
-                    }
+            }
+            Instruction::SkipIfNotPressed { x } => {
+                if self.keypad.get_key(self.registers.v[x as usize] & 0xF) == 0 {
+                    self.program_counter_state = ProgramCounterState::Skip;
                 }
             }
-            0xE000 => match opcode & 0x00FF {
-                0x009E => {
-                    if self.keypad.get_key(self.registers.v[x]) != 0 {
-                        self.program_counter_state = ProgramCounterState::Skip;
-                    }
-                    self.current_instruction = Instructions::SkipIfPressed;
+            Instruction::SetLongI => {
+                self.registers.i = self.read_word(pc as usize + 2)?;
+                self.program_counter_state = ProgramCounterState::Jump(pc.wrapping_add(4));
+            }
+            Instruction::SelectPlanes { x } => self.display.set_plane_mask(x),
+            Instruction::LoadAudioPattern => {
+                let address = self.registers.i as usize;
+                for offset in 0..AUDIO_PATTERN_SIZE {
+                    self.audio_pattern[offset] = self.read_memory(address + offset)?;
                 }
-                0x00A1 => {
-                    if self.keypad.get_key(self.registers.v[x]) == 0 {
-                        self.program_counter_state = ProgramCounterState::Skip;
+            }
+            Instruction::SetVxToDelayTimer { x } => {
+                self.registers.v[x as usize] = self.timers.get_delay_timer();
+            }
+            Instruction::WaitForKeyPress { x } => {
+                let mut key_pressed = false;
+                for i in 0..self.keypad.len() {
+                    if self.keypad.get_key(i as u8) != 0 {
+                        self.registers.v[x as usize] = i as u8;
+                        key_pressed = true;
                     }
-                    self.current_instruction = Instructions::SkipIfNotPressed;
-                }
-                _ => {
-                    self.state = CpuState::Halted;
-                    self.current_instruction = Instructions::Unknown;
                 }
-            },
-            0xF000 => match opcode & 0x00FF {
-                0x0007 => {
-                    self.registers.v[x] = self.timers.get_delay_timer();
-                    self.current_instruction = Instructions::SetVxToDelayTimer;
-                }
-                0x000A => {
-                    let mut key_pressed = false;
-                    for i in 0..self.keypad.len() {
-                        if self.keypad.get_key(i as u8) != 0 {
-                            self.registers.v[x] = i as u8;
-                            key_pressed = true;
-                        }
-                    }
-                    if !key_pressed {
-                        self.program_counter_state = ProgramCounterState::Jump(self.registers.pc - 2);
-                    }
-                    self.current_instruction = Instructions::WaitForKeyPress;
+                if !key_pressed {
+                    // wait on this instruction until a key is pressed
+                    self.program_counter_state = ProgramCounterState::Jump(pc);
                 }
-                0x0015 => {
-                    self.timers.set_delay_timer(self.registers.v[x]);
-                    self.current_instruction = Instructions::SetDelayTimer;
+            }
+            Instruction::SetDelayTimer { x } => {
+                self.timers.set_delay_timer(self.registers.v[x as usize]);
+            }
+            Instruction::SetSoundTimer { x } => {
+                self.timers.set_sound_timer(self.registers.v[x as usize]);
+            }
+            Instruction::AddVxToI { x } => {
+                self.registers.i = self.registers.i.wrapping_add(self.registers.v[x as usize] as u16);
+                // only meaningful with 4K of memory, XO-CHIP addresses 64K and leaves vF alone
+                if !self.has_xo_chip_instructions() {
+                    self.registers.v[0xF] = (self.registers.i > 0xFFF) as u8;
                 }
-                0x0018 => {
-                    self.timers.set_sound_timer(self.registers.v[x]);
-                    self.current_instruction = Instructions::SetSoundTimer;
+            }
+            Instruction::SetIToSprite { x } => {
+                self.registers.i = self.registers.v[x as usize] as u16 * 5;
+            }
+            Instruction::SetIToBigSprite { x } => {
+                let digit = (self.registers.v[x as usize] & 0xF) as usize;
+                self.registers.i = (BIG_FONTSET_ADDRESS + digit * 10) as u16;
+            }
+            Instruction::StoreBCD { x } => {
+                let value = self.registers.v[x as usize];
+                let address = self.registers.i as usize;
+                self.write_memory(address, value / 100)?;
+                self.write_memory(address + 1, (value / 10) % 10)?;
+                self.write_memory(address + 2, (value % 100) % 10)?;
+            }
+            Instruction::SetPitch { x } => self.pitch = self.registers.v[x as usize],
+            Instruction::StoreRegisters { x } => {
+                for i in 0..x as usize + 1 {
+                    self.write_memory(self.registers.i as usize + i, self.registers.v[i])?;
                 }
-                0x001E => {
-                    self.registers.i = self.registers.i.wrapping_add(self.registers.v[x] as u16);
-                    self.registers.v[0xF] = (self.registers.i > 0xFFF) as u8;
-                    self.current_instruction = Instructions::AddVxToI;
+                if self.quirks.load_store_increments_i {
+                    self.registers.i = self.registers.i.wrapping_add(x as u16 + 1);
                 }
-                0x0029 => {
-                    self.registers.i = self.registers.v[x] as u16 * 5;
-                    self.current_instruction = Instructions::SetIToSprite;
+            }
+            Instruction::LoadRegisters { x } => {
+                for i in 0..x as usize + 1 {
+                    self.registers.v[i] = self.read_memory(self.registers.i as usize + i)?;
                 }
-                0x0033 => {
-                    self.ram[self.registers.i as usize] = self.registers.v[x] / 100;
-                    self.ram[self.registers.i as usize + 1] = (self.registers.v[x] / 10) % 10;
-                    self.ram[self.registers.i as usize + 2] = (self.registers.v[x] % 100) % 10;
-                    self.current_instruction = Instructions::StoreBCD;
+                if self.quirks.load_store_increments_i {
+                    self.registers.i = self.registers.i.wrapping_add(x as u16 + 1);
                 }
-                0x0055 => {
-                    for i in 0..x + 1 {
-                        self.ram[self.registers.i as usize + i] = self.registers.v[i];
-                    }
-                    self.registers.i += x as u16 + 1;
-                    self.current_instruction = Instructions::StoreRegisters;
+            }
+            Instruction::StoreFlags { x } => {
+                let count = self.rpl_flag_count(x as usize);
+                self.rpl_flags[..count].copy_from_slice(&self.registers.v[..count]);
+            }
+            Instruction::LoadFlags { x } => {
+                let count = self.rpl_flag_count(x as usize);
+                self.registers.v[..count].copy_from_slice(&self.rpl_flags[..count]);
+            }
+            Instruction::Unknown { .. } => return Err(VmError::UnknownOpcode { pc, opcode }),
+        }
+        Ok(())
+    }
+
+    fn draw_sprite(&mut self, x: usize, y: usize, n: usize) -> Result<(), VmError> {
+        self.registers.v[0xF] = 0;
+
+        let (width, height) = (self.display.width(), self.display.height());
+        // DXY0 draws a 16x16 sprite stored as two bytes per row
+        let (sprite_width, sprite_height) = if n == 0 && self.has_super_chip_instructions() {
+            (16, 16)
+        } else {
+            (8, n)
+        };
+        let bytes_per_row = sprite_width / 8;
+
+        // the starting position always wraps, only the sprite itself is clipped
+        let origin_x = self.registers.v[x] as usize % width;
+        let origin_y = self.registers.v[y] as usize % height;
+        self.last_sprite = Some(SpriteDraw {
+            address: self.registers.i,
+            x: origin_x as u8,
+            y: origin_y as u8,
+            width: sprite_width as u8,
+            height: sprite_height as u8,
+        });
+        // each selected plane reads its own copy of the sprite, one after the other
+        let mut address = self.registers.i as usize;
+        for plane in [0b01, 0b10] {
+            if self.display.plane_mask() & plane == 0 {
+                continue;
+            }
+            for yline in 0..sprite_height {
+                let row_address = address + yline * bytes_per_row;
+                let mut pixels = (self.read_memory(row_address)? as u16) << 8;
+                if bytes_per_row == 2 {
+                    pixels |= self.read_memory(row_address + 1)? as u16;
                 }
-                0x0065 => {
-                    for i in 0..x + 1 {
-                        self.registers.v[i] = self.ram[self.registers.i as usize + i];
+                for xline in 0..sprite_width {
+                    if (pixels & (0x8000 >> xline)) != 0 {
+                        let x_coord = origin_x + xline;
+                        let y_coord = origin_y + yline;
+                        if self.quirks.clip_sprites && (x_coord >= width || y_coord >= height) {
+                            continue;
+                        }
+                        let x_coord = x_coord % width;
+                        let y_coord = y_coord % height;
+                        if self.display.buffer[y_coord][x_coord] & plane != 0 {
+                            // collision detected
+                            self.registers.v[0xF] = 1;
+                        }
+                        self.display.draw_pixel(x_coord, y_coord, plane);
                     }
-                    self.registers.i += x as u16 + 1;
-                    self.current_instruction = Instructions::LoadRegisters;
                 }
-                _ => {
-                    self.state = CpuState::Halted;
-                    self.current_instruction = Instructions::Unknown;
-                }
-            },
-            _ => {
-                self.state = CpuState::Halted;
-                self.current_instruction = Instructions::Unknown;
             }
+            address += sprite_height * bytes_per_row;
         }
-        self.state
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chip8_vm::quirks::Platform;
     #[test]
     fn test_load_rom() {
-        let mut chip8 = Chip8VM::new();
+        let mut chip8 = Chip8VM::new(Quirks::default());
         let rom = vec![0x00, 0x01, 0x02, 0x03];
-        chip8.load_rom(rom);
+        chip8.load_rom(rom).unwrap();
         assert_eq!(chip8.ram[0x200], 0x00);
         assert_eq!(chip8.ram[0x201], 0x01);
         assert_eq!(chip8.ram[0x202], 0x02);
         assert_eq!(chip8.ram[0x203], 0x03);
     }
+    #[test]
+    fn test_load_rom_too_large() {
+        let mut chip8 = Chip8VM::new(Quirks::default());
+        assert!(chip8.load_rom(vec![0; 0xE00]).is_ok());
+        assert!(matches!(
+            chip8.load_rom(vec![0; 0xE01]),
+            Err(RomError::TooLarge { size: 0xE01, max_size: 0xE00 })
+        ));
+
+        chip8.set_platform(Platform::XoChip);
+        assert!(chip8.load_rom(vec![0; 0xE01]).is_ok());
+    }
+
     #[test]
     fn test_load_fontset() {
-        let mut chip8 = Chip8VM::new();
+        let mut chip8 = Chip8VM::new(Quirks::default());
         chip8.init_fontset();
 
         for (i, byte) in FONTSET.iter().enumerate() {
@@ -425,21 +945,21 @@ mod tests {
 
     #[test]
     fn test_read_opcode() {
-        let mut chip8 = Chip8VM::new();
+        let mut chip8 = Chip8VM::new(Quirks::default());
         let rom = vec![0x00, 0x01, 0x02, 0x03, 0x02, 0x03];
-        chip8.load_rom(rom);
-        assert_eq!(chip8.fetch(), 0x0001);
+        chip8.load_rom(rom).unwrap();
+        assert_eq!(chip8.fetch(), Ok(0x0001));
         chip8.registers.pc += 0x2;
-        assert_eq!(chip8.fetch(), 0x0203);
+        assert_eq!(chip8.fetch(), Ok(0x0203));
         chip8.registers.pc += 0x2;
-        assert_eq!(chip8.fetch(), 0x0203);
+        assert_eq!(chip8.fetch(), Ok(0x0203));
     }
 
     #[test]
     fn test_read_memory() {
-        let mut chip8 = Chip8VM::new();
+        let mut chip8 = Chip8VM::new(Quirks::default());
         let rom = vec![0x00, 0x01, 0x02, 0x03];
-        chip8.load_rom(rom);
+        chip8.load_rom(rom).unwrap();
         let mut memory = [0; MEMORY_SIZE];
         memory[0x200] = 0x00;
         memory[0x201] = 0x01;
@@ -447,4 +967,386 @@ mod tests {
         memory[0x203] = 0x03;
         assert_eq!(chip8.ram, memory);
     }
+
+    fn run_opcodes(quirks: Quirks, opcodes: &[u16]) -> Chip8VM {
+        let mut chip8 = Chip8VM::new(quirks);
+        chip8.init_fontset();
+        chip8.signal_vblank();
+        let rom = opcodes.iter().flat_map(|op| op.to_be_bytes()).collect();
+        chip8.load_rom(rom).unwrap();
+        for _ in opcodes {
+            chip8.cycle().unwrap();
+        }
+        chip8
+    }
+
+    #[test]
+    fn test_shift_quirk() {
+        let opcodes = [0x6003, 0x6181, 0x8016];
+        let chip8 = run_opcodes(Quirks::from_platform(Platform::Chip8), &opcodes);
+        assert_eq!(chip8.registers.v[0], 0x40);
+        assert_eq!(chip8.registers.v[0xF], 1);
+
+        let chip8 = run_opcodes(Quirks::from_platform(Platform::SuperChip), &opcodes);
+        assert_eq!(chip8.registers.v[0], 0x01);
+        assert_eq!(chip8.registers.v[0xF], 1);
+    }
+
+    #[test]
+    fn test_load_store_quirk() {
+        let opcodes = [0xA300, 0xF255];
+        let chip8 = run_opcodes(Quirks::from_platform(Platform::Chip8), &opcodes);
+        assert_eq!(chip8.registers.i, 0x303);
+
+        let chip8 = run_opcodes(Quirks::from_platform(Platform::SuperChip), &opcodes);
+        assert_eq!(chip8.registers.i, 0x300);
+    }
+
+    #[test]
+    fn test_jump_quirk() {
+        let opcodes = [0x6004, 0x6208, 0xB220];
+        let chip8 = run_opcodes(Quirks::from_platform(Platform::Chip8), &opcodes);
+        assert_eq!(chip8.registers.pc, 0x224);
+
+        let chip8 = run_opcodes(Quirks::from_platform(Platform::SuperChip), &opcodes);
+        assert_eq!(chip8.registers.pc, 0x228);
+    }
+
+    #[test]
+    fn test_logic_quirk() {
+        let opcodes = [0x6F05, 0x8011];
+        let chip8 = run_opcodes(Quirks::from_platform(Platform::Chip8), &opcodes);
+        assert_eq!(chip8.registers.v[0xF], 0);
+
+        let chip8 = run_opcodes(Quirks::from_platform(Platform::SuperChip), &opcodes);
+        assert_eq!(chip8.registers.v[0xF], 5);
+    }
+
+    #[test]
+    fn test_clip_quirk() {
+        // draw the "0" glyph at (62, 30)
+        let opcodes = [0x603E, 0x611E, 0xA000, 0xD015];
+        let mut quirks = Quirks::from_platform(Platform::Chip8);
+        let chip8 = run_opcodes(quirks, &opcodes);
+        assert_eq!(chip8.display.buffer[30][62], 1);
+        assert_eq!(chip8.display.buffer[0][62], 0);
+        assert_eq!(chip8.display.buffer[30][0], 0);
+
+        quirks.clip_sprites = false;
+        let chip8 = run_opcodes(quirks, &opcodes);
+        assert_eq!(chip8.display.buffer[30][62], 1);
+        assert_eq!(chip8.display.buffer[0][62], 1);
+        assert_eq!(chip8.display.buffer[30][0], 1);
+    }
+
+    #[test]
+    fn test_display_wait_quirk() {
+        let opcodes = [0xD001, 0xD001];
+        let chip8 = run_opcodes(Quirks::from_platform(Platform::Chip8), &opcodes);
+        assert_eq!(chip8.registers.pc, 0x202);
+
+        let chip8 = run_opcodes(Quirks::from_platform(Platform::SuperChip), &opcodes);
+        assert_eq!(chip8.registers.pc, 0x204);
+    }
+
+    fn run_schip_opcodes(opcodes: &[u16]) -> Chip8VM {
+        let mut chip8 = Chip8VM::new(Quirks::from_platform(Platform::SuperChip));
+        chip8.set_platform(Platform::SuperChip);
+        chip8.init_fontset();
+        let rom = opcodes.iter().flat_map(|op| op.to_be_bytes()).collect();
+        chip8.load_rom(rom).unwrap();
+        for _ in opcodes {
+            chip8.cycle().unwrap();
+        }
+        chip8
+    }
+
+    #[test]
+    fn test_schip_hires_big_sprite() {
+        // 16x16 sprite made of the first 32 bytes of the big font, drawn at (120, 60)
+        let chip8 = run_schip_opcodes(&[0x00FF, 0x6078, 0x613C, 0xA050, 0xD010]);
+        assert!(chip8.display.is_hires());
+        assert_eq!((chip8.display.width(), chip8.display.height()), (128, 64));
+        // 0x3C7E: the first row starts with two blank pixels
+        assert_eq!(chip8.display.buffer[60][120], 0);
+        assert_eq!(chip8.display.buffer[60][122], 1);
+        assert_eq!(chip8.display.buffer[60][125], 1);
+        assert_eq!(chip8.display.buffer[60][127], 0);
+        // clipped at the bottom-right corner
+        assert_eq!(chip8.display.buffer[0][0], 0);
+        assert_eq!(
+            chip8.get_last_sprite(),
+            Some(SpriteDraw {
+                address: 0x50,
+                x: 120,
+                y: 60,
+                width: 16,
+                height: 16,
+            })
+        );
+    }
+
+    #[test]
+    fn test_schip_scroll() {
+        let chip8 = run_schip_opcodes(&[0x00FF, 0xA000, 0xD001, 0x00C2, 0x00FB]);
+        // 0xF0 drawn at the origin, then moved 2 down and 4 right
+        assert_eq!(chip8.display.buffer[0][0], 0);
+        assert_eq!(chip8.display.buffer[2][3], 0);
+        assert_eq!(chip8.display.buffer[2][4], 1);
+        assert_eq!(chip8.display.buffer[2][7], 1);
+        assert_eq!(chip8.display.buffer[2][8], 0);
+
+        let chip8 = run_schip_opcodes(&[0xA000, 0x6008, 0xD011, 0x00FC]);
+        assert_eq!(chip8.display.buffer[0][4], 1);
+        assert_eq!(chip8.display.buffer[0][8], 0);
+    }
+
+    #[test]
+    fn test_schip_big_font_and_flags() {
+        let chip8 = run_schip_opcodes(&[0x6009, 0xF030, 0x6A2A, 0xFA75, 0x6A00, 0xFA85]);
+        assert_eq!(chip8.registers.i as usize, BIG_FONTSET_ADDRESS + 90);
+        assert_eq!(chip8.rpl_flags[0], 9);
+        // only V0..V7 are persisted on SUPER-CHIP
+        assert_eq!(chip8.rpl_flags[0xA], 0);
+        assert_eq!(chip8.registers.v[0xA], 0);
+    }
+
+    fn run_xo_chip_words(words: &[u16], steps: usize) -> Chip8VM {
+        let mut chip8 = Chip8VM::new(Quirks::from_platform(Platform::XoChip));
+        chip8.set_platform(Platform::XoChip);
+        chip8.init_fontset();
+        let rom = words.iter().flat_map(|op| op.to_be_bytes()).collect();
+        chip8.load_rom(rom).unwrap();
+        for _ in 0..steps {
+            chip8.cycle().unwrap();
+        }
+        chip8
+    }
+
+    #[test]
+    fn test_xo_chip_long_i_and_skip() {
+        let chip8 = run_xo_chip_words(&[0xF000, 0xABCD, 0x3000, 0xF000, 0x1234, 0x6001], 3);
+        assert_eq!(chip8.ram.len(), XO_CHIP_MEMORY_SIZE);
+        assert_eq!(chip8.registers.i, 0xABCD);
+        // the skip jumped over the whole four byte instruction
+        assert_eq!(chip8.registers.v[0], 1);
+        assert_eq!(chip8.registers.pc, 0x20C);
+    }
+
+    #[test]
+    fn test_xo_chip_add_to_i_keeps_vf() {
+        let chip8 = run_xo_chip_words(&[0xF000, 0x1000, 0x6F05, 0x6010, 0xF01E], 4);
+        assert_eq!(chip8.registers.i, 0x1010);
+        assert_eq!(chip8.registers.v[0xF], 5);
+
+        let chip8 = run_opcodes(Quirks::default(), &[0xAFF0, 0x6F05, 0x6010, 0xF01E]);
+        assert_eq!(chip8.registers.i, 0x1000);
+        assert_eq!(chip8.registers.v[0xF], 1);
+    }
+
+    #[test]
+    fn test_xo_chip_register_ranges() {
+        let chip8 = run_xo_chip_words(&[0x6001, 0x6102, 0x6203, 0xA300, 0x5212, 0x5013], 6);
+        assert_eq!(&chip8.ram[0x300..0x302], &[3, 2]);
+        assert_eq!(chip8.registers.v[..3], [3, 2, 3]);
+        assert_eq!(chip8.registers.i, 0x300);
+    }
+
+    #[test]
+    fn test_xo_chip_planes() {
+        // select both planes and draw a one-row sprite per plane: 0xF0 then 0x0F
+        let chip8 = run_xo_chip_words(&[0xF301, 0xA20A, 0xD001, 0xF201, 0x00E0, 0xF00F], 5);
+        assert_eq!(chip8.display.plane_mask(), 0b10);
+        // the second plane was cleared, the first one kept its pixels
+        assert_eq!(chip8.display.buffer[0][0], 0b01);
+        assert_eq!(chip8.display.buffer[0][4], 0);
+    }
+
+    #[test]
+    fn test_xo_chip_audio() {
+        let chip8 = run_xo_chip_words(&[0xA000, 0xF002, 0x6070, 0xF03A], 4);
+        assert_eq!(chip8.get_audio_pattern(), FONTSET[..16]);
+        assert_eq!(chip8.get_pitch(), 0x70);
+        assert!(chip8.get_playback_rate() > 4000.0);
+    }
+
+    #[test]
+    fn test_unknown_opcode_halts() {
+        let mut chip8 = Chip8VM::new(Quirks::default());
+        chip8.load_rom(vec![0x60, 0x01, 0xFF, 0xFF]).unwrap();
+        assert!(chip8.cycle().is_ok());
+        let error = VmError::UnknownOpcode { pc: 0x202, opcode: 0xFFFF };
+        assert_eq!(chip8.cycle(), Err(error));
+        assert_eq!(chip8.get_state(), CpuState::Halted);
+        assert_eq!(chip8.get_fault(), Some(error));
+        // the VM stays halted until it is reset
+        assert_eq!(chip8.cycle(), Err(error));
+        assert_eq!(chip8.get_pc(), 0x202);
+
+        chip8.reset();
+        assert_eq!(chip8.get_fault(), None);
+    }
+
+    #[test]
+    fn test_skip_on_key_uses_low_nibble() {
+        let mut chip8 = Chip8VM::new(Quirks::default());
+        // v0 := 0x10, skip if key 0 is pressed
+        chip8.load_rom(vec![0x60, 0x10, 0xE0, 0x9E]).unwrap();
+        chip8.keypad.set_key(0, 1);
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.get_pc(), 0x206);
+    }
+
+    #[test]
+    fn test_memory_out_of_bounds() {
+        let mut chip8 = Chip8VM::new(Quirks::default());
+        chip8.load_rom(vec![0xAF, 0xFE, 0xF2, 0x55]).unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(
+            chip8.cycle(),
+            Err(VmError::MemoryOutOfBounds { pc: 0x202, addr: 0x1000 })
+        );
+
+        let mut chip8 = Chip8VM::new(Quirks::default());
+        chip8.load_rom(vec![0x1F, 0xFF]).unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.cycle(), Err(VmError::PcOutOfBounds { pc: 0xFFF }));
+    }
+
+    #[test]
+    fn test_step_info() {
+        let mut chip8 = Chip8VM::new(Quirks::default());
+        chip8.load_rom(vec![0x6A, 0x2A]).unwrap();
+        let step = chip8.cycle().unwrap();
+        assert_eq!(step.pc, 0x200);
+        assert_eq!(step.opcode, 0x6A2A);
+        assert_eq!(step.instruction, Instruction::SetVx { x: 0xA, nn: 0x2A });
+    }
+
+    #[test]
+    fn test_nested_calls() {
+        // 200: call 206, 202: jump 202, 206: call 20A, 208: return, 20A: return
+        let mut chip8 = Chip8VM::new(Quirks::default());
+        chip8
+            .load_rom(vec![0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x22, 0x0A, 0x00, 0xEE, 0x00, 0xEE])
+            .unwrap();
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.get_call_stack(), &[0x202, 0x208]);
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.get_pc(), 0x208);
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.get_pc(), 0x202);
+        assert!(chip8.get_call_stack().is_empty());
+    }
+
+    #[test]
+    fn test_stack_overflow_and_underflow() {
+        // a subroutine calling itself forever
+        let mut chip8 = Chip8VM::new(Quirks::default());
+        chip8.load_rom(vec![0x22, 0x00]).unwrap();
+        for _ in 0..VIP_STACK_DEPTH {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.cycle(), Err(VmError::StackOverflow { pc: 0x200 }));
+
+        let mut chip8 = Chip8VM::new(Quirks::default());
+        chip8.set_stack_depth(32);
+        chip8.load_rom(vec![0x22, 0x00]).unwrap();
+        for _ in 0..32 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.get_call_stack().len(), 32);
+
+        let mut chip8 = Chip8VM::new(Quirks::default());
+        chip8.load_rom(vec![0x00, 0xEE]).unwrap();
+        assert_eq!(chip8.cycle(), Err(VmError::StackUnderflow { pc: 0x200 }));
+    }
+
+    #[test]
+    fn test_seeded_random() {
+        let random_bytes = |seed: u64| {
+            let mut chip8 = Chip8VM::new(Quirks::default());
+            chip8.set_seed(seed);
+            chip8.load_rom(vec![0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0x0F]).unwrap();
+            for _ in 0..3 {
+                chip8.cycle().unwrap();
+            }
+            chip8.get_v()
+        };
+        assert_eq!(random_bytes(7), random_bytes(7));
+        assert_ne!(random_bytes(7), random_bytes(8));
+        assert!(random_bytes(7)[2] <= 0x0F);
+
+        // a reset replays the sequence from the seed
+        let mut chip8 = Chip8VM::new(Quirks::default());
+        chip8.set_seed(7);
+        chip8.load_rom(vec![0xC0, 0xFF]).unwrap();
+        chip8.cycle().unwrap();
+        let first = chip8.get_v()[0];
+        chip8.reset();
+        chip8.load_rom(vec![0xC0, 0xFF]).unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.get_v()[0], first);
+    }
+
+    #[test]
+    fn test_load_state_sections() {
+        let chip8 = run_opcodes(Quirks::default(), &[0x6A2A, 0xA123]);
+        let mut writer = StateWriter::new(&[0; ROM_HASH_SIZE]);
+        chip8.save_state(&mut writer);
+        let state = writer.finish();
+
+        // an older state with only some sections leaves the rest alone
+        let mut older = StateWriter::new(&[0; ROM_HASH_SIZE]);
+        older.section(b"TIMR", &[30, 0]);
+        let older = older.finish();
+        let mut restored = Chip8VM::new(Quirks::default());
+        restored.load_state(&StateReader::new(&state).unwrap()).unwrap();
+        restored.load_state(&StateReader::new(&older).unwrap()).unwrap();
+        assert_eq!(restored.get_v()[0xA], 0x2A);
+        assert_eq!(restored.get_i(), 0x123);
+        assert_eq!(restored.get_pc(), 0x204);
+        assert_eq!(restored.timers.get_delay_timer(), 30);
+
+        // a bad section rolls back everything loaded before it
+        let mut broken = StateWriter::new(&[0; ROM_HASH_SIZE]);
+        broken.section(b"CPU ", &[0xFF; 22]);
+        broken.section(b"RAM ", &[0; 16]);
+        let broken = broken.finish();
+        assert_eq!(
+            restored.load_state(&StateReader::new(&broken).unwrap()),
+            Err(SaveStateError::Truncated)
+        );
+        assert_eq!(restored.get_v()[0xA], 0x2A);
+    }
+
+    #[test]
+    fn test_access_log() {
+        // i := 0x300, v0 := 123, bcd v0, load v1
+        let mut chip8 = Chip8VM::new(Quirks::default());
+        chip8.load_rom(vec![0xA3, 0x00, 0x60, 0x7B, 0xF0, 0x33, 0xF1, 0x65]).unwrap();
+        chip8.cycle().unwrap();
+        chip8.set_access_logging(true);
+        chip8.cycle().unwrap();
+        assert!(chip8.get_last_accesses().is_empty());
+
+        chip8.cycle().unwrap();
+        let writes: Vec<usize> = chip8.get_last_accesses().iter().map(|access| access.address).collect();
+        assert_eq!(writes, vec![0x300, 0x301, 0x302]);
+        assert!(chip8
+            .get_last_accesses()
+            .iter()
+            .all(|access| access.kind == AccessKind::Write));
+
+        chip8.cycle().unwrap();
+        assert_eq!(
+            chip8.get_last_accesses(),
+            &[
+                MemoryAccess { address: 0x300, kind: AccessKind::Read },
+                MemoryAccess { address: 0x301, kind: AccessKind::Read },
+            ]
+        );
+    }
 }
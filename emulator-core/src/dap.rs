@@ -0,0 +1,785 @@
+//! A Debug Adapter Protocol server, so ROMs can be debugged from editors that
+//! speak it. Messages are read from `input` and written to `output`, stdio when
+//! started by an editor.
+//!
+//! `launch` takes the ROM as `program`, plus optional `stopOnEntry` and
+//! `platform`. Octo source (`.8o`) is assembled with a source map, which allows
+//! breakpoints by line and shows source lines in the stack trace; any ROM can
+//! have breakpoints by address through instruction breakpoints. While running,
+//! the emulator keeps to 60 frames a second.
+
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::assembler::{self, Program};
+use crate::chip8_vm::quirks::{Platform, Quirks};
+use crate::database::RomOverrides;
+use crate::debugger::{Debugger, Register, StopReason};
+use crate::Emulator;
+
+/// The VM is the only thread.
+const THREAD_ID: u64 = 1;
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+const REGISTERS_REFERENCE: u64 = 1;
+const TIMERS_REFERENCE: u64 = 2;
+const STACK_REFERENCE: u64 = 3;
+
+/// Serves one client until it disconnects or `input` ends.
+pub fn serve(input: impl Read + Send + 'static, output: impl Write) -> io::Result<()> {
+    // read on a thread of its own, so requests like pause arrive while running
+    let (sender, messages) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(input);
+        while let Some(message) = read_message(&mut reader).transpose() {
+            let failed = message.is_err();
+            if sender.send(message).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    let mut emulator = Emulator::new(Quirks::from_platform(Platform::Chip8));
+    emulator.set_platform(Platform::Chip8);
+    let mut session = Session {
+        output,
+        seq: 0,
+        emulator,
+        debugger: Debugger::new(),
+        source: None,
+        source_breakpoints: BTreeSet::new(),
+        instruction_breakpoints: BTreeSet::new(),
+        launched: false,
+        stop_on_entry: false,
+        running: false,
+        deadline: Instant::now(),
+        done: false,
+    };
+    session.run(messages)
+}
+
+/// Reads one `Content-Length` framed message, or `None` at the end of `reader`.
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length.ok_or_else(|| invalid_data("message without a Content-Length"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|error| invalid_data(&error.to_string()))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+struct SourceMap {
+    path: PathBuf,
+    program: Program,
+}
+
+struct Session<W> {
+    output: W,
+    seq: u64,
+    emulator: Emulator,
+    debugger: Debugger,
+    source: Option<SourceMap>,
+    source_breakpoints: BTreeSet<u16>,
+    instruction_breakpoints: BTreeSet<u16>,
+    launched: bool,
+    stop_on_entry: bool,
+    running: bool,
+    /// When the next frame is due while running.
+    deadline: Instant,
+    done: bool,
+}
+
+impl<W: Write> Session<W> {
+    fn run(&mut self, messages: Receiver<io::Result<Value>>) -> io::Result<()> {
+        while !self.done {
+            let message = if self.running {
+                let frame = self.emulator.get_frame_count() + 1;
+                let reason = self.debugger.run_until_frame(&mut self.emulator, frame);
+                if !matches!(reason, StopReason::Frame { .. }) {
+                    self.running = false;
+                    self.stopped(stop_reason_name(&reason), &reason.to_string())?;
+                    continue;
+                }
+                // run late rather than catch up after a stall
+                let now = Instant::now();
+                self.deadline = (self.deadline + FRAME_DURATION).max(now);
+                match messages.recv_timeout(self.deadline - now) {
+                    Ok(message) => message,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            } else {
+                match messages.recv() {
+                    Ok(message) => message,
+                    Err(_) => break,
+                }
+            };
+            self.handle(&message?)?;
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, message: &Value) -> io::Result<()> {
+        if message["type"] != "request" {
+            return Ok(());
+        }
+        let command = message["command"].as_str().unwrap_or_default();
+        let args = &message["arguments"];
+        let result = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsReadMemoryRequest": true,
+                "supportsWriteMemoryRequest": true,
+                "supportsTerminateRequest": true,
+            })),
+            "launch" => self.launch(args),
+            "setBreakpoints" => Ok(self.set_breakpoints(args)),
+            "setInstructionBreakpoints" => Ok(self.set_instruction_breakpoints(args)),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "configurationDone" => Ok(Value::Null),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(json!({
+                "scopes": [
+                    { "name": "Registers", "variablesReference": REGISTERS_REFERENCE, "expensive": false },
+                    { "name": "Timers", "variablesReference": TIMERS_REFERENCE, "expensive": false },
+                    { "name": "Stack", "variablesReference": STACK_REFERENCE, "expensive": false },
+                ]
+            })),
+            "variables" => Ok(self.variables(args["variablesReference"].as_u64().unwrap_or(0))),
+            "readMemory" => self.read_memory(args),
+            "writeMemory" => self.write_memory(args),
+            "continue" | "next" | "stepIn" | "stepOut" | "pause" if !self.launched => {
+                Err("no program has been launched".to_string())
+            }
+            "continue" => Ok(json!({ "allThreadsContinued": true })),
+            "next" | "stepIn" | "stepOut" | "pause" => Ok(Value::Null),
+            "disconnect" | "terminate" => {
+                self.done = true;
+                Ok(Value::Null)
+            }
+            _ => Err(format!("unsupported request '{}'", command)),
+        };
+        let succeeded = result.is_ok();
+        self.respond(message, result)?;
+        if !succeeded {
+            return Ok(());
+        }
+
+        // what follows the response
+        match command {
+            "initialize" => self.event("initialized", Value::Null)?,
+            "configurationDone" if self.launched => {
+                if self.stop_on_entry {
+                    self.stopped("entry", "entry")?;
+                } else {
+                    self.resume();
+                }
+            }
+            "continue" => self.resume(),
+            "pause" if self.running => {
+                self.running = false;
+                self.stopped("pause", "paused")?;
+            }
+            "next" | "stepIn" | "stepOut" => {
+                self.running = false;
+                let reason = match command {
+                    "next" => self.debugger.step_over(&mut self.emulator),
+                    "stepIn" => self.debugger.step(&mut self.emulator),
+                    _ => self.debugger.step_out(&mut self.emulator),
+                };
+                self.stopped(stop_reason_name(&reason), &reason.to_string())?;
+            }
+            "terminate" => self.event("terminated", Value::Null)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let path = PathBuf::from(
+            args["program"]
+                .as_str()
+                .ok_or("launch needs the ROM as 'program'")?,
+        );
+        let mut overrides = RomOverrides::default();
+        if let Some(platform) = args["platform"].as_str() {
+            overrides.platform = Some(platform.parse()?);
+        }
+        self.emulator.set_overrides(overrides);
+        self.emulator
+            .load_rom(path.clone())
+            .map_err(|error| format!("{}: {}", path.display(), error))?;
+
+        self.source = if path.extension().is_some_and(|extension| extension == "8o") {
+            let source = std::fs::read_to_string(&path).map_err(|error| error.to_string())?;
+            let program = assembler::assemble(&source).map_err(|error| error.to_string())?;
+            Some(SourceMap { path, program })
+        } else {
+            None
+        };
+        self.launched = true;
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        Ok(Value::Null)
+    }
+
+    fn resume(&mut self) {
+        self.running = true;
+        self.deadline = Instant::now();
+    }
+
+    /// Replaces the breakpoints in the launched source. A line without code gets
+    /// its breakpoint on the next line that has some.
+    fn set_breakpoints(&mut self, args: &Value) -> Value {
+        let lines: Vec<usize> = args["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|breakpoint| breakpoint["line"].as_u64())
+            .map(|line| line as usize)
+            .collect();
+        let path = args["source"]["path"].as_str().map(PathBuf::from);
+        let program = self
+            .source
+            .as_ref()
+            .filter(|source| path.is_some_and(|path| same_file(&path, &source.path)))
+            .map(|source| &source.program);
+
+        let mut addresses = BTreeSet::new();
+        let breakpoints: Vec<Value> = lines
+            .iter()
+            .map(|line| {
+                let Some(program) = program else {
+                    return json!({ "verified": false, "line": line, "message": "no source map for this file" });
+                };
+                let last_line = program.lines.values().copied().max().unwrap_or(0);
+                let found = (*line..=last_line).find_map(|line| {
+                    let code = program.line_addresses(line);
+                    (!code.is_empty()).then_some((line, code))
+                });
+                match found {
+                    Some((line, code)) => {
+                        addresses.extend(&code);
+                        json!({
+                            "verified": true,
+                            "line": line,
+                            "instructionReference": format_address(code[0]),
+                        })
+                    }
+                    None => json!({ "verified": false, "line": line, "message": "no code at or after this line" }),
+                }
+            })
+            .collect();
+
+        // a client sets the breakpoints of every open file, only keep ours
+        if program.is_some() || self.source.is_none() {
+            self.source_breakpoints = addresses;
+            self.sync_breakpoints();
+        }
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Value {
+        let breakpoints: Vec<Value> = args["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|breakpoint| {
+                let offset = breakpoint["offset"].as_i64().unwrap_or(0);
+                let address = breakpoint["instructionReference"]
+                    .as_str()
+                    .and_then(parse_address)
+                    .map(|address| address.wrapping_add(offset as u64) as u16);
+                match address {
+                    Some(address) => json!({
+                        "verified": true,
+                        "instructionReference": format_address(address),
+                    }),
+                    None => json!({ "verified": false, "message": "invalid instruction reference" }),
+                }
+            })
+            .collect();
+        self.instruction_breakpoints = breakpoints
+            .iter()
+            .filter_map(|breakpoint| breakpoint["instructionReference"].as_str())
+            .filter_map(parse_address)
+            .map(|address| address as u16)
+            .collect();
+        self.sync_breakpoints();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn sync_breakpoints(&mut self) {
+        for address in self.debugger.breakpoints().collect::<Vec<_>>() {
+            self.debugger.remove_breakpoint(address);
+        }
+        for address in self.source_breakpoints.union(&self.instruction_breakpoints) {
+            self.debugger.add_breakpoint(*address);
+        }
+    }
+
+    /// The current instruction, then the call of every subroutine in progress.
+    fn stack_trace(&self) -> Value {
+        let vm = &self.emulator.chip8_vm;
+        let calls = vm.get_call_stack().iter().rev();
+        let addresses = std::iter::once(vm.get_pc()).chain(calls.map(|ret| ret.wrapping_sub(2)));
+        let frames: Vec<Value> = addresses
+            .enumerate()
+            .map(|(id, address)| {
+                let mut frame = json!({
+                    "id": id,
+                    "name": self.frame_name(address),
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format_address(address),
+                });
+                if let Some(source) = &self.source {
+                    if let Some(line) = source.program.lines.get(&address) {
+                        frame["line"] = json!(line);
+                        frame["column"] = json!(1);
+                        frame["source"] = json!({
+                            "name": source.path.file_name().map(|name| name.to_string_lossy()),
+                            "path": source.path,
+                        });
+                    }
+                }
+                frame
+            })
+            .collect();
+        json!({ "stackFrames": frames, "totalFrames": frames.len() })
+    }
+
+    /// The nearest label at or before `address`, or the address itself.
+    fn frame_name(&self, address: u16) -> String {
+        let label = self.source.as_ref().and_then(|source| {
+            source
+                .program
+                .labels
+                .iter()
+                .filter(|(_, label)| **label <= address)
+                .max_by_key(|(_, label)| **label)
+        });
+        match label {
+            Some((name, label)) if *label == address => name.clone(),
+            Some((name, label)) => format!("{}+0x{:x}", name, address - label),
+            None => format_address(address),
+        }
+    }
+
+    fn variables(&self, reference: u64) -> Value {
+        let vm = &self.emulator.chip8_vm;
+        let variable = |name: String, value: String| {
+            json!({ "name": name, "value": value, "variablesReference": 0 })
+        };
+        let pointer = |name: &str, address: u16| {
+            json!({
+                "name": name,
+                "value": format_address(address),
+                "variablesReference": 0,
+                "memoryReference": format_address(address),
+            })
+        };
+        let variables: Vec<Value> = match reference {
+            REGISTERS_REFERENCE => {
+                let mut registers: Vec<Value> = vm
+                    .get_v()
+                    .iter()
+                    .enumerate()
+                    .map(|(x, value)| {
+                        variable(Register::V(x as u8).to_string(), format!("0x{:02x}", value))
+                    })
+                    .collect();
+                registers.push(pointer("i", vm.get_i()));
+                registers.push(pointer("pc", vm.get_pc()));
+                registers
+            }
+            TIMERS_REFERENCE => vec![
+                variable("delay".to_string(), vm.timers.get_delay_timer().to_string()),
+                variable("sound".to_string(), vm.timers.get_sound_timer().to_string()),
+            ],
+            STACK_REFERENCE => vm
+                .get_call_stack()
+                .iter()
+                .rev()
+                .enumerate()
+                .map(|(depth, ret)| pointer(&depth.to_string(), *ret))
+                .collect(),
+            _ => Vec::new(),
+        };
+        json!({ "variables": variables })
+    }
+
+    fn memory_address(args: &Value) -> Result<usize, String> {
+        let reference = args["memoryReference"].as_str().unwrap_or_default();
+        let address = parse_address(reference)
+            .ok_or_else(|| format!("invalid memory reference '{}'", reference))?;
+        Ok(address.wrapping_add(args["offset"].as_i64().unwrap_or(0) as u64) as usize)
+    }
+
+    fn read_memory(&self, args: &Value) -> Result<Value, String> {
+        let address = Self::memory_address(args)?;
+        let count = args["count"].as_u64().unwrap_or(0) as usize;
+        let ram = self.emulator.chip8_vm.get_ram();
+        let start = address.min(ram.len());
+        let end = address.saturating_add(count).min(ram.len());
+        Ok(json!({
+            "address": format_address(address as u16),
+            "data": encode_base64(&ram[start..end]),
+            "unreadableBytes": count - (end - start),
+        }))
+    }
+
+    fn write_memory(&mut self, args: &Value) -> Result<Value, String> {
+        let address = Self::memory_address(args)?;
+        let data = args["data"]
+            .as_str()
+            .and_then(decode_base64)
+            .ok_or("invalid base64 data")?;
+        let ram = self.emulator.chip8_vm.get_ram_mut();
+        let target = address
+            .checked_add(data.len())
+            .and_then(|end| ram.get_mut(address..end));
+        let Some(target) = target else {
+            return Err(format!("{} bytes at {} are out of memory", data.len(), address));
+        };
+        target.copy_from_slice(&data);
+        Ok(json!({ "bytesWritten": data.len() }))
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(Value::Null) => {}
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn stopped(&mut self, reason: &str, description: &str) -> io::Result<()> {
+        let body = json!({
+            "reason": reason,
+            "description": description,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        self.event("stopped", body)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        let mut message = json!({ "type": "event", "event": event });
+        if !body.is_null() {
+            message["body"] = body;
+        }
+        self.send(message)
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.output, &message)
+    }
+}
+
+fn stop_reason_name(reason: &StopReason) -> &'static str {
+    match reason {
+        StopReason::Step => "step",
+        StopReason::Breakpoint { .. } => "breakpoint",
+        StopReason::MemoryWatchpoint { .. } | StopReason::RegisterWatchpoint { .. } => {
+            "data breakpoint"
+        }
+        StopReason::Frame { .. } => "pause",
+        StopReason::Fault(_) => "exception",
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    a == b || matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
+
+fn format_address(address: u16) -> String {
+    format!("0x{:03x}", address)
+}
+
+fn parse_address(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (index, byte)| bits | (*byte as u32) << (16 - 8 * index));
+        for index in 0..4 {
+            if index <= chunk.len() {
+                text.push(BASE64[(bits >> (18 - 6 * index) & 0x3F) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut bits = 0u32;
+    let mut count = 0;
+    for character in text.bytes().filter(|character| *character != b'=') {
+        let value = BASE64.iter().position(|c| *c == character)? as u32;
+        bits = bits << 6 | value;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::mpsc::Sender;
+
+    const SOURCE: &str = "\
+: main
+  v0 := 0
+  loop
+    v0 += 1
+    add-two
+  again
+: add-two
+  v1 += 2
+  return
+";
+
+    struct ChannelReader {
+        receiver: Receiver<Vec<u8>>,
+        buffer: VecDeque<u8>,
+    }
+
+    impl Read for ChannelReader {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            if self.buffer.is_empty() {
+                match self.receiver.recv() {
+                    Ok(bytes) => self.buffer.extend(bytes),
+                    Err(_) => return Ok(0),
+                }
+            }
+            self.buffer.read(buffer)
+        }
+    }
+
+    struct ChannelWriter(Sender<Vec<u8>>);
+
+    impl Write for ChannelWriter {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            self.0
+                .send(buffer.to_vec())
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+            Ok(buffer.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn channel() -> (ChannelWriter, ChannelReader) {
+        let (sender, receiver) = mpsc::channel();
+        let reader = ChannelReader {
+            receiver,
+            buffer: VecDeque::new(),
+        };
+        (ChannelWriter(sender), reader)
+    }
+
+    struct Client {
+        output: ChannelWriter,
+        input: BufReader<ChannelReader>,
+        seq: u64,
+        events: VecDeque<Value>,
+    }
+
+    impl Client {
+        fn request(&mut self, command: &str, arguments: Value) -> Value {
+            self.seq += 1;
+            let request = json!({
+                "seq": self.seq,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            });
+            write_message(&mut self.output, &request).unwrap();
+            loop {
+                let message = read_message(&mut self.input).unwrap().unwrap();
+                if message["type"] == "response" {
+                    assert_eq!(message["request_seq"], self.seq);
+                    return message;
+                }
+                self.events.push_back(message);
+            }
+        }
+
+        fn body(&mut self, command: &str, arguments: Value) -> Value {
+            let response = self.request(command, arguments);
+            assert_eq!(response["success"], true, "{}", response);
+            response["body"].clone()
+        }
+
+        fn event(&mut self) -> Value {
+            self.events
+                .pop_front()
+                .unwrap_or_else(|| read_message(&mut self.input).unwrap().unwrap())
+        }
+
+        fn stopped(&mut self) -> String {
+            let event = self.event();
+            assert_eq!(event["event"], "stopped");
+            event["body"]["reason"].as_str().unwrap().to_string()
+        }
+
+        fn top_line(&mut self) -> Value {
+            self.body("stackTrace", json!({ "threadId": THREAD_ID }))["stackFrames"][0]["line"]
+                .clone()
+        }
+    }
+
+    #[test]
+    fn test_scripted_session() {
+        let path = std::env::temp_dir().join(format!("dap-{}.8o", std::process::id()));
+        std::fs::write(&path, SOURCE).unwrap();
+
+        let (to_server, server_input) = channel();
+        let (server_output, from_server) = channel();
+        let server = thread::spawn(move || serve(server_input, server_output));
+        let mut client = Client {
+            output: to_server,
+            input: BufReader::new(from_server),
+            seq: 0,
+            events: VecDeque::new(),
+        };
+
+        let capabilities = client.body("initialize", json!({ "adapterID": "chip8" }));
+        assert_eq!(capabilities["supportsReadMemoryRequest"], true);
+        assert_eq!(client.event()["event"], "initialized");
+        assert_eq!(client.request("continue", json!({}))["success"], false);
+        client.body("launch", json!({ "program": path, "stopOnEntry": true }));
+
+        // the loop line has no code, so its breakpoint moves to the next line
+        let breakpoints = client.body(
+            "setBreakpoints",
+            json!({ "source": { "path": path }, "breakpoints": [{ "line": 3 }, { "line": 8 }] }),
+        );
+        assert_eq!(breakpoints["breakpoints"][0]["line"], 4);
+        assert_eq!(breakpoints["breakpoints"][1]["verified"], true);
+        client.body("configurationDone", Value::Null);
+        assert_eq!(client.stopped(), "entry");
+        assert_eq!(client.top_line(), 2);
+
+        client.body("continue", json!({ "threadId": THREAD_ID }));
+        assert_eq!(client.stopped(), "breakpoint");
+        assert_eq!(client.top_line(), 4);
+        client.body("continue", json!({ "threadId": THREAD_ID }));
+        assert_eq!(client.stopped(), "breakpoint");
+        let trace = client.body("stackTrace", json!({ "threadId": THREAD_ID }));
+        let frames = &trace["stackFrames"];
+        assert_eq!(frames[0]["name"], "add-two");
+        assert_eq!(frames[1]["name"], "main+0x4");
+        assert_eq!(frames[1]["line"], 5);
+
+        client.body("stepOut", json!({ "threadId": THREAD_ID }));
+        assert_eq!(client.stopped(), "step");
+        assert_eq!(client.top_line(), 6);
+        let registers = client.body("variables", json!({ "variablesReference": REGISTERS_REFERENCE }));
+        assert_eq!(registers["variables"][0]["value"], "0x01");
+        assert_eq!(registers["variables"][1]["value"], "0x02");
+        assert_eq!(registers["variables"][17]["name"], "pc");
+
+        // by address instead
+        client.body("setBreakpoints", json!({ "source": { "path": path }, "breakpoints": [] }));
+        client.body(
+            "setInstructionBreakpoints",
+            json!({ "breakpoints": [{ "instructionReference": "0x20a" }] }),
+        );
+        client.body("next", json!({ "threadId": THREAD_ID }));
+        assert_eq!(client.stopped(), "step");
+        client.body("continue", json!({ "threadId": THREAD_ID }));
+        assert_eq!(client.stopped(), "breakpoint");
+        assert_eq!(client.top_line(), 9);
+        let stack = client.body("variables", json!({ "variablesReference": STACK_REFERENCE }));
+        assert_eq!(stack["variables"][0]["value"], "0x206");
+
+        let memory = client.body("readMemory", json!({ "memoryReference": "0x200", "count": 4 }));
+        assert_eq!(memory["data"], "YABwAQ==");
+        client.body("writeMemory", json!({ "memoryReference": "0x300", "data": "qrs=" }));
+        let memory = client.body("readMemory", json!({ "memoryReference": "0x2ff", "offset": 1, "count": 2 }));
+        assert_eq!(memory["data"], "qrs=");
+        let write = client.request("writeMemory", json!({ "memoryReference": "0x0", "offset": -1, "data": "qrs=" }));
+        assert_eq!(write["success"], false);
+
+        client.body("setInstructionBreakpoints", json!({ "breakpoints": [] }));
+        client.body("continue", json!({ "threadId": THREAD_ID }));
+        client.body("pause", json!({ "threadId": THREAD_ID }));
+        assert_eq!(client.stopped(), "pause");
+
+        assert_eq!(client.request("evaluate", json!({}))["success"], false);
+        client.body("disconnect", json!({}));
+        server.join().unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_base64_round_trips() {
+        for length in 0..8 {
+            let bytes: Vec<u8> = (0..length).map(|byte| byte * 37 + 1).collect();
+            assert_eq!(decode_base64(&encode_base64(&bytes)), Some(bytes));
+        }
+        assert_eq!(encode_base64(b"chip"), "Y2hpcA==");
+    }
+}
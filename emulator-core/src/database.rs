@@ -0,0 +1,272 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::chip8_vm::display::{Palette, DEFAULT_PALETTE};
+use crate::chip8_vm::quirks::{Platform, Quirks};
+use crate::chip8_vm::savestate::ROM_HASH_SIZE;
+
+/// A subset of the community CHIP-8 database's `programs.json`, for the ROMs in `roms/`.
+/// The full file can be loaded with `RomDatabase::from_path`.
+const BUNDLED_PROGRAMS: &str = include_str!("database/programs.json");
+
+#[derive(Debug)]
+pub enum DatabaseError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    InvalidHash(String),
+    InvalidColor(String),
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatabaseError::Io(error) => write!(f, "could not read ROM database: {}", error),
+            DatabaseError::Json(error) => write!(f, "invalid ROM database: {}", error),
+            DatabaseError::InvalidHash(hash) => write!(f, "invalid SHA-1 '{}' in ROM database", hash),
+            DatabaseError::InvalidColor(color) => {
+                write!(f, "invalid color '{}' in ROM database", color)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+impl From<std::io::Error> for DatabaseError {
+    fn from(error: std::io::Error) -> Self {
+        DatabaseError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for DatabaseError {
+    fn from(error: serde_json::Error) -> Self {
+        DatabaseError::Json(error)
+    }
+}
+
+#[derive(Deserialize)]
+struct ProgramEntry {
+    title: String,
+    #[serde(default)]
+    roms: HashMap<String, RomEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RomEntry {
+    /// Platform ids, best first.
+    #[serde(default)]
+    platforms: Vec<String>,
+    tickrate: Option<u32>,
+    /// Quirks that differ from the platform's, by platform id and quirk name.
+    #[serde(default)]
+    quirky_platforms: HashMap<String, HashMap<String, bool>>,
+    #[serde(default)]
+    keys: BTreeMap<String, u8>,
+    colors: Option<ColorsEntry>,
+}
+
+#[derive(Deserialize)]
+struct ColorsEntry {
+    /// `#rrggbb` for each combination of planes.
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+/// The recommended configuration for a known ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomInfo {
+    pub title: String,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    /// Instructions per frame.
+    pub tickrate: Option<u32>,
+    /// What the game uses each key for, e.g. "up" to 5.
+    pub keys: BTreeMap<String, u8>,
+    pub palette: Option<Palette>,
+}
+
+/// Settings the user chose, which win over the database's.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RomOverrides {
+    /// Also selects the platform's preset quirks unless `quirks` is set.
+    pub platform: Option<Platform>,
+    pub quirks: Option<Quirks>,
+    pub instructions_per_second: Option<u32>,
+    pub palette: Option<Palette>,
+}
+
+/// Maps a database platform id to ours, with the quirks the database gives it.
+/// `None` for platforms this emulator doesn't run.
+fn platform_from_id(id: &str) -> Option<(Platform, Quirks)> {
+    let platform = match id {
+        "originalChip8" | "hybridVIP" | "modernChip8" => Platform::Chip8,
+        "chip48" => Platform::Chip48,
+        "superchip1" | "superchip" => Platform::SuperChip,
+        "xochip" => Platform::XoChip,
+        _ => return None,
+    };
+    let mut quirks = Quirks::from_platform(platform);
+    if id == "modernChip8" {
+        quirks.logic_resets_vf = false;
+        quirks.display_wait = false;
+    }
+    Some((platform, quirks))
+}
+
+fn apply_quirk(quirks: &mut Quirks, name: &str, value: bool) {
+    match name {
+        "shift" => quirks.shift_uses_vy = !value,
+        "memoryLeaveIUnchanged" => quirks.load_store_increments_i = !value,
+        "wrap" => quirks.clip_sprites = !value,
+        "jump" => quirks.jump_uses_vx = value,
+        "vblank" => quirks.display_wait = value,
+        "logic" => quirks.logic_resets_vf = value,
+        // memoryIncrementByX has no equivalent, it is the same as incrementing for most programs
+        _ => {}
+    }
+}
+
+fn parse_hash(hex: &str) -> Result<[u8; ROM_HASH_SIZE], DatabaseError> {
+    let invalid = || DatabaseError::InvalidHash(hex.to_string());
+    if hex.len() != ROM_HASH_SIZE * 2 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let mut hash = [0; ROM_HASH_SIZE];
+    for (index, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(hash)
+}
+
+fn parse_color(text: &str) -> Result<[u8; 4], DatabaseError> {
+    let invalid = || DatabaseError::InvalidColor(text.to_string());
+    let hex = text.strip_prefix('#').ok_or_else(invalid)?;
+    let rgb = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+    if hex.len() != 6 {
+        return Err(invalid());
+    }
+    Ok([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8, 255])
+}
+
+/// Known ROMs by SHA-1, read from the community CHIP-8 database's `programs.json`.
+pub struct RomDatabase {
+    roms: HashMap<[u8; ROM_HASH_SIZE], RomInfo>,
+}
+
+impl RomDatabase {
+    pub fn bundled() -> Self {
+        Self::from_json(BUNDLED_PROGRAMS).expect("bundled ROM database is valid")
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self, DatabaseError> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parses a `programs.json`. ROMs only listed for platforms we don't run are left out.
+    pub fn from_json(json: &str) -> Result<Self, DatabaseError> {
+        let programs: Vec<ProgramEntry> = serde_json::from_str(json)?;
+        let mut roms = HashMap::new();
+        for program in programs {
+            for (hash, entry) in program.roms {
+                let Some((id, (platform, mut quirks))) = entry
+                    .platforms
+                    .iter()
+                    .find_map(|id| platform_from_id(id).map(|platform| (id, platform)))
+                else {
+                    continue;
+                };
+                for (name, value) in entry.quirky_platforms.get(id).into_iter().flatten() {
+                    apply_quirk(&mut quirks, name, *value);
+                }
+                let palette = match entry.colors {
+                    Some(colors) if !colors.pixels.is_empty() => {
+                        let mut palette = DEFAULT_PALETTE;
+                        for (color, text) in palette.iter_mut().zip(&colors.pixels) {
+                            *color = parse_color(text)?;
+                        }
+                        Some(palette)
+                    }
+                    _ => None,
+                };
+                roms.insert(
+                    parse_hash(&hash)?,
+                    RomInfo {
+                        title: program.title.clone(),
+                        platform,
+                        quirks,
+                        tickrate: entry.tickrate,
+                        keys: entry.keys,
+                        palette,
+                    },
+                );
+            }
+        }
+        Ok(RomDatabase { roms })
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+
+    pub fn lookup(&self, hash: &[u8; ROM_HASH_SIZE]) -> Option<&RomInfo> {
+        self.roms.get(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAMS: &str = r##"[
+        {
+            "title": "Some Game",
+            "release": "1991",
+            "roms": {
+                "00112233445566778899aabbccddeeff00112233": {
+                    "file": "game.ch8",
+                    "platforms": ["megachip8", "superchip"],
+                    "tickrate": 30,
+                    "quirkyPlatforms": { "superchip": { "shift": false, "wrap": true } },
+                    "keys": { "left": 4, "right": 6 },
+                    "colors": { "pixels": ["#102030", "#ffcc00"] }
+                },
+                "ffffffffffffffffffffffffffffffffffffffff": {
+                    "platforms": ["megachip8"]
+                }
+            }
+        }
+    ]"##;
+
+    #[test]
+    fn test_lookup_applies_platform_quirks() {
+        let database = RomDatabase::from_json(PROGRAMS).unwrap();
+        assert_eq!(database.len(), 1);
+        let info = database
+            .lookup(&parse_hash("00112233445566778899aabbccddeeff00112233").unwrap())
+            .unwrap();
+        assert_eq!(info.title, "Some Game");
+        assert_eq!(info.platform, Platform::SuperChip);
+        assert!(info.quirks.shift_uses_vy);
+        assert!(!info.quirks.clip_sprites);
+        assert!(info.quirks.jump_uses_vx);
+        assert_eq!(info.tickrate, Some(30));
+        assert_eq!(info.keys.get("right"), Some(&6));
+        let palette = info.palette.unwrap();
+        assert_eq!(palette[0], [0x10, 0x20, 0x30, 255]);
+        assert_eq!(palette[2], DEFAULT_PALETTE[2]);
+
+        assert!(RomDatabase::bundled().len() >= 2);
+        assert!(matches!(
+            RomDatabase::from_json(r#"[{"title": "x", "roms": {"abc": {"platforms": ["xochip"]}}}]"#),
+            Err(DatabaseError::InvalidHash(_))
+        ));
+    }
+}
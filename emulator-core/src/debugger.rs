@@ -0,0 +1,399 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::Range;
+
+use crate::chip8_vm::error::VmError;
+use crate::chip8_vm::instruction::Instruction;
+use crate::chip8_vm::vm::{AccessKind, MemoryAccess, StepInfo};
+use crate::Emulator;
+
+/// Ten seconds at 60 frames per second.
+pub const DEFAULT_RUN_LIMIT: u64 = 600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Register {
+    V(u8),
+    I,
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::V(x) => write!(f, "v{:x}", x),
+            Register::I => write!(f, "i"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    fn matches(self, kind: AccessKind) -> bool {
+        match self {
+            WatchKind::Read => kind == AccessKind::Read,
+            WatchKind::Write => kind == AccessKind::Write,
+            WatchKind::ReadWrite => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryWatchpoint {
+    pub range: Range<usize>,
+    pub kind: WatchKind,
+}
+
+/// Why the debugger handed control back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The requested step, step over or step out completed.
+    Step,
+    /// The next instruction to run is at a breakpoint.
+    Breakpoint { pc: u16 },
+    /// The instruction at `pc` touched watched memory.
+    MemoryWatchpoint { pc: u16, access: MemoryAccess },
+    /// The instruction at `pc` changed a watched register.
+    RegisterWatchpoint {
+        pc: u16,
+        register: Register,
+        old: u16,
+        new: u16,
+    },
+    /// The frame to run until was reached, or the run limit ran out.
+    Frame { frame: u64 },
+    Fault(VmError),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Step => write!(f, "step"),
+            StopReason::Breakpoint { pc } => write!(f, "breakpoint at {:03X}", pc),
+            StopReason::MemoryWatchpoint { pc, access } => write!(
+                f,
+                "{} of {:03X} by {:03X}",
+                match access.kind {
+                    AccessKind::Read => "read",
+                    AccessKind::Write => "write",
+                },
+                access.address,
+                pc
+            ),
+            StopReason::RegisterWatchpoint {
+                pc,
+                register,
+                old,
+                new,
+            } => write!(
+                f,
+                "{} changed from {:X} to {:X} at {:03X}",
+                register, old, new, pc
+            ),
+            StopReason::Frame { frame } => write!(f, "reached frame {}", frame),
+            StopReason::Fault(error) => write!(f, "VM fault: {}", error),
+        }
+    }
+}
+
+/// Breakpoints and watchpoints for running an `Emulator` under control.
+///
+/// Every run stops at the first of: a fault, a watchpoint hit by the instruction
+/// just run, the run's own goal, or a breakpoint on the next instruction. Runs
+/// that could go on forever, like stepping over a subroutine that never returns,
+/// give up after `run_limit` frames.
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    memory_watchpoints: Vec<MemoryWatchpoint>,
+    register_watchpoints: BTreeSet<Register>,
+    run_limit: u64,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            memory_watchpoints: Vec::new(),
+            register_watchpoints: BTreeSet::new(),
+            run_limit: DEFAULT_RUN_LIMIT,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, pc: u16) {
+        self.breakpoints.insert(pc);
+    }
+
+    /// Returns whether there was a breakpoint at `pc`.
+    pub fn remove_breakpoint(&mut self, pc: u16) -> bool {
+        self.breakpoints.remove(&pc)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_memory_watchpoint(&mut self, range: Range<usize>, kind: WatchKind) {
+        self.memory_watchpoints.push(MemoryWatchpoint { range, kind });
+    }
+
+    /// Removes the watchpoints covering exactly `range`, returning whether there were any.
+    pub fn remove_memory_watchpoint(&mut self, range: Range<usize>) -> bool {
+        let count = self.memory_watchpoints.len();
+        self.memory_watchpoints.retain(|watchpoint| watchpoint.range != range);
+        self.memory_watchpoints.len() != count
+    }
+
+    pub fn memory_watchpoints(&self) -> &[MemoryWatchpoint] {
+        &self.memory_watchpoints
+    }
+
+    pub fn add_register_watchpoint(&mut self, register: Register) {
+        self.register_watchpoints.insert(register);
+    }
+
+    pub fn remove_register_watchpoint(&mut self, register: Register) -> bool {
+        self.register_watchpoints.remove(&register)
+    }
+
+    pub fn register_watchpoints(&self) -> impl Iterator<Item = Register> + '_ {
+        self.register_watchpoints.iter().copied()
+    }
+
+    /// Most frames a step over or step out runs before giving up.
+    pub fn set_run_limit(&mut self, frames: u64) {
+        self.run_limit = frames.max(1);
+    }
+
+    /// Runs a single instruction.
+    pub fn step(&self, emulator: &mut Emulator) -> StopReason {
+        let last_frame = emulator.get_frame_count() + self.run_limit;
+        self.run(emulator, last_frame, |_, _| true)
+    }
+
+    /// Runs a single instruction, or a whole subroutine if it is a call.
+    pub fn step_over(&self, emulator: &mut Emulator) -> StopReason {
+        let pc = emulator.chip8_vm.get_pc();
+        let depth = emulator.chip8_vm.get_call_stack().len();
+        let last_frame = emulator.get_frame_count() + self.run_limit;
+        let mut calling = false;
+        self.run(emulator, last_frame, |emulator, step| {
+            if step.pc == pc {
+                calling |= matches!(step.instruction, Instruction::Call { .. });
+            }
+            !calling
+                || (emulator.chip8_vm.get_pc() == pc.wrapping_add(2)
+                    && emulator.chip8_vm.get_call_stack().len() == depth)
+        })
+    }
+
+    /// Runs until the current subroutine returns.
+    pub fn step_out(&self, emulator: &mut Emulator) -> StopReason {
+        let depth = emulator.chip8_vm.get_call_stack().len();
+        let last_frame = emulator.get_frame_count() + self.run_limit;
+        self.run(emulator, last_frame, |emulator, step| {
+            step.instruction == Instruction::Return
+                && emulator.chip8_vm.get_call_stack().len() < depth
+        })
+    }
+
+    /// Runs until `frame` frames have completed since the ROM started.
+    pub fn run_until_frame(&self, emulator: &mut Emulator, frame: u64) -> StopReason {
+        self.run(emulator, frame, |_, _| false)
+    }
+
+    fn run(
+        &self,
+        emulator: &mut Emulator,
+        last_frame: u64,
+        mut done: impl FnMut(&Emulator, &StepInfo) -> bool,
+    ) -> StopReason {
+        emulator
+            .chip8_vm
+            .set_access_logging(!self.memory_watchpoints.is_empty());
+        loop {
+            if emulator.get_frame_count() >= last_frame {
+                return StopReason::Frame {
+                    frame: emulator.get_frame_count(),
+                };
+            }
+
+            let before = self.watched_registers(emulator);
+            let step = match emulator.step() {
+                Ok(Some(step)) => step,
+                // a frame went by without running anything
+                Ok(None) => continue,
+                Err(error) => return StopReason::Fault(error),
+            };
+
+            if let Some(access) = self.memory_watchpoint_hit(emulator) {
+                return StopReason::MemoryWatchpoint {
+                    pc: step.pc,
+                    access,
+                };
+            }
+            let after = self.watched_registers(emulator);
+            if let Some(((register, old), (_, new))) =
+                before.into_iter().zip(after).find(|(old, new)| old != new)
+            {
+                return StopReason::RegisterWatchpoint {
+                    pc: step.pc,
+                    register,
+                    old,
+                    new,
+                };
+            }
+            if done(emulator, &step) {
+                return StopReason::Step;
+            }
+            let pc = emulator.chip8_vm.get_pc();
+            if self.breakpoints.contains(&pc) {
+                return StopReason::Breakpoint { pc };
+            }
+        }
+    }
+
+    fn watched_registers(&self, emulator: &Emulator) -> Vec<(Register, u16)> {
+        let v = emulator.chip8_vm.get_v();
+        self.register_watchpoints
+            .iter()
+            .map(|register| {
+                let value = match register {
+                    Register::V(x) => v[*x as usize & 0xF] as u16,
+                    Register::I => emulator.chip8_vm.get_i(),
+                };
+                (*register, value)
+            })
+            .collect()
+    }
+
+    fn memory_watchpoint_hit(&self, emulator: &Emulator) -> Option<MemoryAccess> {
+        emulator
+            .chip8_vm
+            .get_last_accesses()
+            .iter()
+            .find(|access| {
+                self.memory_watchpoints.iter().any(|watchpoint| {
+                    watchpoint.range.contains(&access.address) && watchpoint.kind.matches(access.kind)
+                })
+            })
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8_vm::quirks::Quirks;
+    use crate::Rom;
+
+    /// ```text
+    /// 200: call 20A
+    /// 202: v1 += 1
+    /// 204: i := 300
+    /// 206: save v1
+    /// 208: jump 200
+    /// 20A: v0 += 1
+    /// 20C: call 210
+    /// 20E: return
+    /// 210: v2 += 1
+    /// 212: return
+    /// ```
+    const ROM: [u8; 20] = [
+        0x22, 0x0A, 0x71, 0x01, 0xA3, 0x00, 0xF1, 0x55, 0x12, 0x00, 0x70, 0x01, 0x22, 0x10,
+        0x00, 0xEE, 0x72, 0x01, 0x00, 0xEE,
+    ];
+
+    fn emulator() -> Emulator {
+        let mut emulator = Emulator::new(Quirks::default());
+        emulator
+            .insert_rom(Rom::from_bytes("debug".to_string(), ROM.to_vec()))
+            .unwrap();
+        emulator
+    }
+
+    #[test]
+    fn test_steps() {
+        let mut emulator = emulator();
+        let debugger = Debugger::new();
+        assert_eq!(debugger.step(&mut emulator), StopReason::Step);
+        assert_eq!(emulator.chip8_vm.get_pc(), 0x20A);
+
+        // into the nested call, out of both subroutines
+        assert_eq!(debugger.step(&mut emulator), StopReason::Step);
+        assert_eq!(debugger.step(&mut emulator), StopReason::Step);
+        assert_eq!(emulator.chip8_vm.get_pc(), 0x210);
+        assert_eq!(debugger.step_out(&mut emulator), StopReason::Step);
+        assert_eq!(emulator.chip8_vm.get_pc(), 0x20E);
+        assert_eq!(debugger.step_out(&mut emulator), StopReason::Step);
+        assert_eq!(emulator.chip8_vm.get_pc(), 0x202);
+
+        // around the loop and over the call
+        for _ in 0..4 {
+            debugger.step_over(&mut emulator);
+        }
+        assert_eq!(emulator.chip8_vm.get_pc(), 0x200);
+        assert_eq!(debugger.step_over(&mut emulator), StopReason::Step);
+        assert_eq!(emulator.chip8_vm.get_pc(), 0x202);
+        assert_eq!(emulator.chip8_vm.get_v()[2], 2);
+    }
+
+    #[test]
+    fn test_breakpoints_and_watchpoints() {
+        let mut emulator = emulator();
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x210);
+        assert_eq!(
+            debugger.run_until_frame(&mut emulator, 10),
+            StopReason::Breakpoint { pc: 0x210 }
+        );
+        // stepping over a call stops at breakpoints inside it
+        assert!(debugger.remove_breakpoint(0x210));
+        debugger.add_breakpoint(0x212);
+        assert_eq!(
+            debugger.step_out(&mut emulator),
+            StopReason::Breakpoint { pc: 0x212 }
+        );
+        debugger.remove_breakpoint(0x212);
+
+        debugger.add_memory_watchpoint(0x300..0x301, WatchKind::Write);
+        assert_eq!(
+            debugger.run_until_frame(&mut emulator, 10),
+            StopReason::MemoryWatchpoint {
+                pc: 0x206,
+                access: MemoryAccess {
+                    address: 0x300,
+                    kind: AccessKind::Write
+                }
+            }
+        );
+        debugger.remove_memory_watchpoint(0x300..0x301);
+
+        debugger.add_register_watchpoint(Register::V(1));
+        assert_eq!(
+            debugger.run_until_frame(&mut emulator, 10),
+            StopReason::RegisterWatchpoint {
+                pc: 0x202,
+                register: Register::V(1),
+                old: 1,
+                new: 2
+            }
+        );
+        debugger.remove_register_watchpoint(Register::V(1));
+
+        assert_eq!(
+            debugger.run_until_frame(&mut emulator, 10),
+            StopReason::Frame { frame: 10 }
+        );
+        assert_eq!(emulator.get_frame_count(), 10);
+    }
+}
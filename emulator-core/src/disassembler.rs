@@ -0,0 +1,333 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::chip8_vm::instruction::{decode, Instruction};
+use crate::chip8_vm::quirks::Platform;
+use crate::Rom;
+
+const PROGRAM_START_ADDRESS: u16 = 0x200;
+const DATA_BYTES_PER_LINE: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    Label(String),
+    Instruction { address: u16, text: String },
+    /// Bytes referenced by `i :=`, rendered one sprite row per line.
+    Sprite { address: u16, bytes: Vec<u8> },
+    Data { address: u16, bytes: Vec<u8> },
+}
+
+/// A ROM split into code and data, with labels on every branch and `i` target.
+pub struct Disassembly {
+    pub lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    Data,
+    Code,
+    Subroutine,
+}
+
+struct Walker<'a> {
+    rom: &'a [u8],
+    platform: Platform,
+    /// Address of every instruction reached, with its decoded form.
+    code: BTreeMap<u16, Instruction>,
+    is_code: Vec<bool>,
+    labels: BTreeMap<u16, LabelKind>,
+}
+
+pub fn disassemble(rom: &Rom, platform: Platform) -> Disassembly {
+    disassemble_bytes(rom.get_data(), platform)
+}
+
+/// Bytes that would load past the end of the 64K address space are left out.
+pub fn disassemble_bytes(rom: &[u8], platform: Platform) -> Disassembly {
+    let rom = &rom[..rom.len().min(0x10000 - PROGRAM_START_ADDRESS as usize)];
+    let mut walker = Walker {
+        rom,
+        platform,
+        code: BTreeMap::new(),
+        is_code: vec![false; rom.len()],
+        labels: BTreeMap::new(),
+    };
+    walker.add_label(PROGRAM_START_ADDRESS, LabelKind::Code);
+    walker.walk(PROGRAM_START_ADDRESS);
+    walker.into_disassembly()
+}
+
+impl<'a> Walker<'a> {
+    fn word(&self, address: u16) -> Option<u16> {
+        let offset = address.checked_sub(PROGRAM_START_ADDRESS)? as usize;
+        let hi = *self.rom.get(offset)? as u16;
+        let lo = *self.rom.get(offset + 1)? as u16;
+        Some((hi << 8) | lo)
+    }
+
+    fn supports(&self, instruction: Instruction) -> bool {
+        match instruction.extension() {
+            Some(Platform::XoChip) => self.platform == Platform::XoChip,
+            Some(_) => matches!(self.platform, Platform::SuperChip | Platform::XoChip),
+            None => !matches!(instruction, Instruction::Unknown { .. }),
+        }
+    }
+
+    fn add_label(&mut self, address: u16, kind: LabelKind) {
+        let label = self.labels.entry(address).or_insert(kind);
+        *label = (*label).max(kind);
+    }
+
+    /// Follows every path from `start`, marking the instructions it reaches as code.
+    fn walk(&mut self, start: u16) {
+        let mut pending = vec![start];
+        while let Some(address) = pending.pop() {
+            if self.code.contains_key(&address) {
+                continue;
+            }
+            let Some(opcode) = self.word(address) else {
+                continue;
+            };
+            let instruction = decode(opcode);
+            if !self.supports(instruction) {
+                continue;
+            }
+            let size = instruction.size();
+            let offset = (address - PROGRAM_START_ADDRESS) as usize;
+            let end = offset + size as usize;
+            // overlapping an instruction found earlier means this path is going through data
+            if end > self.rom.len() || self.is_code[offset..end].iter().any(|b| *b) {
+                continue;
+            }
+            self.is_code[offset..end].iter_mut().for_each(|b| *b = true);
+            self.code.insert(address, instruction);
+
+            // a path running off the end of the address space stops, like one leaving the ROM
+            let next = address.checked_add(size);
+            match instruction {
+                Instruction::Jump { nnn } => {
+                    self.add_label(nnn, LabelKind::Code);
+                    pending.push(nnn);
+                }
+                Instruction::JumpV0 { nnn, .. } => {
+                    // the table of jumps at the target is the best guess we have
+                    self.add_label(nnn, LabelKind::Code);
+                    pending.push(nnn);
+                }
+                Instruction::Call { nnn } => {
+                    self.add_label(nnn, LabelKind::Subroutine);
+                    pending.push(nnn);
+                    pending.extend(next);
+                }
+                Instruction::Return | Instruction::Exit => {}
+                Instruction::SetI { nnn } => {
+                    self.add_label(nnn, LabelKind::Data);
+                    pending.extend(next);
+                }
+                Instruction::SetLongI => {
+                    if let Some(target) = address.checked_add(2).and_then(|operand| self.word(operand)) {
+                        self.add_label(target, LabelKind::Data);
+                    }
+                    pending.extend(next);
+                }
+                _ if instruction.is_skip() => {
+                    let Some(next) = next else {
+                        continue;
+                    };
+                    pending.push(next);
+                    let skipped = match self.word(next).map(decode) {
+                        Some(Instruction::SetLongI) if self.platform == Platform::XoChip => 4,
+                        _ => 2,
+                    };
+                    pending.extend(next.checked_add(skipped));
+                }
+                _ => pending.extend(next),
+            }
+        }
+    }
+
+    fn label_name(&self, address: u16) -> Option<String> {
+        let kind = self.labels.get(&address)?;
+        // labels in the middle of an instruction can't be expressed
+        let offset = address.checked_sub(PROGRAM_START_ADDRESS)? as usize;
+        if offset >= self.rom.len()
+            || (self.is_code[offset] && !self.code.contains_key(&address))
+        {
+            return None;
+        }
+        if address == PROGRAM_START_ADDRESS {
+            return Some("main".to_string());
+        }
+        Some(match kind {
+            LabelKind::Data => format!("data_{:03x}", address),
+            LabelKind::Code => format!("label_{:03x}", address),
+            LabelKind::Subroutine => format!("sub_{:03x}", address),
+        })
+    }
+
+    fn render_instruction(&self, address: u16, instruction: Instruction) -> String {
+        let target = |nnn: u16| self.label_name(nnn).unwrap_or(format!("0x{:03x}", nnn));
+        match instruction {
+            Instruction::Jump { nnn } => format!("jump {}", target(nnn)),
+            Instruction::JumpV0 { nnn, .. } => format!("jump0 {}", target(nnn)),
+            Instruction::Call { nnn } => match self.label_name(nnn) {
+                Some(label) => label,
+                None => instruction.to_string(),
+            },
+            Instruction::SetI { nnn } => format!("i := {}", target(nnn)),
+            Instruction::SetLongI => {
+                let nnnn = self.word(address + 2).unwrap_or(0);
+                let target = self.label_name(nnnn).unwrap_or(format!("0x{:04x}", nnnn));
+                format!("i := long {}", target)
+            }
+            _ => instruction.to_string(),
+        }
+    }
+
+    fn into_disassembly(self) -> Disassembly {
+        let mut lines = Vec::new();
+        let mut data: Vec<u8> = Vec::new();
+        let mut data_start = PROGRAM_START_ADDRESS;
+        let mut sprite = false;
+
+        let flush = |lines: &mut Vec<Line>, data: &mut Vec<u8>, start: u16, sprite: bool| {
+            if data.is_empty() {
+                return;
+            }
+            let bytes = std::mem::take(data);
+            lines.push(if sprite {
+                Line::Sprite {
+                    address: start,
+                    bytes,
+                }
+            } else {
+                Line::Data {
+                    address: start,
+                    bytes,
+                }
+            });
+        };
+
+        let mut offset = 0;
+        while offset < self.rom.len() {
+            let address = PROGRAM_START_ADDRESS + offset as u16;
+            if let Some(label) = self.label_name(address) {
+                flush(&mut lines, &mut data, data_start, sprite);
+                lines.push(Line::Label(label));
+                sprite = self.labels.get(&address) == Some(&LabelKind::Data);
+            }
+
+            if let Some(instruction) = self.code.get(&address) {
+                flush(&mut lines, &mut data, data_start, sprite);
+                sprite = false;
+                lines.push(Line::Instruction {
+                    address,
+                    text: self.render_instruction(address, *instruction),
+                });
+                offset += instruction.size() as usize;
+                continue;
+            }
+
+            if data.is_empty() {
+                data_start = address;
+            }
+            data.push(self.rom[offset]);
+            if sprite || data.len() == DATA_BYTES_PER_LINE {
+                flush(&mut lines, &mut data, data_start, sprite);
+            }
+            offset += 1;
+        }
+        flush(&mut lines, &mut data, data_start, sprite);
+
+        Disassembly { lines }
+    }
+}
+
+/// Renders the listing in Octo syntax.
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Label(label) => writeln!(f, ": {}", label)?,
+                Line::Instruction { text, .. } => writeln!(f, "\t{}", text)?,
+                Line::Sprite { bytes, .. } => {
+                    for byte in bytes {
+                        let pixels: String = (0..8)
+                            .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+                            .collect();
+                        writeln!(f, "\t0b{:08b} # {}", byte, pixels)?;
+                    }
+                }
+                Line::Data { bytes, .. } => {
+                    let bytes: Vec<String> = bytes.iter().map(|b| format!("0x{:02x}", b)).collect();
+                    writeln!(f, "\t{}", bytes.join(" "))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_separates_code_and_data() {
+        // main: i := sprite, sprite v0 v0 2, jump main; followed by a two byte sprite
+        let rom = [0xA2, 0x06, 0xD0, 0x02, 0x12, 0x00, 0xF0, 0x90];
+        let listing = disassemble_bytes(&rom, Platform::Chip8).to_string();
+        assert_eq!(
+            listing,
+            ": main\n\
+             \ti := data_206\n\
+             \tsprite v0 v0 2\n\
+             \tjump main\n\
+             : data_206\n\
+             \t0b11110000 # ####....\n\
+             \t0b10010000 # #..#....\n"
+        );
+    }
+
+    #[test]
+    fn test_follows_calls_and_skips() {
+        // 200: call 208, 202: if v0 != 1 then, 204: jump 200, 206: exit-less data, 208: return
+        let rom = [0x22, 0x08, 0x30, 0x01, 0x12, 0x00, 0xFF, 0xFF, 0x00, 0xEE];
+        let disassembly = disassemble_bytes(&rom, Platform::Chip8);
+        assert!(disassembly.lines.contains(&Line::Label("sub_208".to_string())));
+        assert!(disassembly.lines.contains(&Line::Instruction {
+            address: 0x200,
+            text: "sub_208".to_string()
+        }));
+        assert!(disassembly.lines.contains(&Line::Data {
+            address: 0x206,
+            bytes: vec![0xFF, 0xFF]
+        }));
+    }
+
+    #[test]
+    fn test_code_up_to_the_end_of_memory() {
+        // a full XO-CHIP ROM of v0 := 0 ending in a plain instruction or a skip at 0xFFFE
+        for last in [[0x60, 0x00], [0x30, 0x00]] {
+            let mut rom = [0x60, 0x00].repeat(0xFE00 / 2);
+            let end = rom.len();
+            rom[end - 2..].copy_from_slice(&last);
+            let disassembly = disassemble_bytes(&rom, Platform::XoChip);
+            assert!(disassembly.lines.contains(&Line::Instruction {
+                address: 0xFFFE,
+                text: decode(u16::from_be_bytes(last)).to_string()
+            }));
+        }
+    }
+
+    #[test]
+    fn test_bytes_past_the_end_of_memory() {
+        let mut rom = [0x60, 0x00].repeat(0xFE00 / 2);
+        rom.extend_from_slice(&[0xAB, 0xCD]);
+        let disassembly = disassemble_bytes(&rom, Platform::XoChip);
+        assert!(matches!(
+            disassembly.lines.last(),
+            Some(Line::Instruction { address: 0xFFFE, .. })
+        ));
+    }
+}
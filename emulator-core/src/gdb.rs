@@ -0,0 +1,564 @@
+//! A stub for GDB's remote serial protocol, so ROMs can be debugged from gdb or
+//! any other frontend that speaks it:
+//!
+//! ```text
+//! (gdb) target remote localhost:9000
+//! ```
+//!
+//! Registers are v0-vf, i, pc, sp and the delay and sound timers, described to the
+//! client through `target.xml`. sp is the depth of the call stack and can't be
+//! written. Memory is the VM's RAM. Continuing runs as fast as the host allows,
+//! until a breakpoint, a watchpoint, a fault or a Ctrl-C from the client.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+
+use crate::chip8_vm::error::VmError;
+use crate::chip8_vm::vm::AccessKind;
+use crate::debugger::{Debugger, StopReason, WatchKind};
+use crate::Emulator;
+
+const NUM_REGISTERS: usize = 21;
+const REGISTER_I: usize = 16;
+const REGISTER_PC: usize = 17;
+const REGISTER_SP: usize = 18;
+const REGISTER_DT: usize = 19;
+const REGISTER_ST: usize = 20;
+
+const INTERRUPT: u8 = 0x03;
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+pub struct GdbServer {
+    listener: TcpListener,
+}
+
+impl GdbServer {
+    /// Listens on localhost only, the protocol has no authentication. Port 0 picks a free one.
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        Ok(GdbServer { listener })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Waits for a client and serves it until it detaches, kills the program or
+    /// disconnects. Breakpoints and watchpoints are kept in `debugger`.
+    pub fn serve(&self, emulator: &mut Emulator, debugger: &mut Debugger) -> io::Result<()> {
+        let (stream, _) = self.listener.accept()?;
+        stream.set_nodelay(true)?;
+        let mut session = Session {
+            stream,
+            input: VecDeque::new(),
+            emulator,
+            debugger,
+        };
+        session.run()
+    }
+}
+
+struct Session<'a> {
+    stream: TcpStream,
+    input: VecDeque<u8>,
+    emulator: &'a mut Emulator,
+    debugger: &'a mut Debugger,
+}
+
+impl Session<'_> {
+    fn run(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            let packet = String::from_utf8_lossy(&packet).into_owned();
+            match self.handle(&packet)? {
+                Some(reply) => self.send_packet(&reply)?,
+                None => return Ok(()),
+            }
+        }
+        Ok(())
+    }
+
+    /// The reply to `packet`, or `None` when the session is over.
+    fn handle(&mut self, packet: &str) -> io::Result<Option<String>> {
+        // invalid UTF-8 arrives as U+FFFD, so the command can be more than one byte
+        let command_len = packet.chars().next().map_or(0, char::len_utf8);
+        let (command, args) = packet.split_at(command_len);
+        let reply = match command {
+            "?" => stop_packet(SIGTRAP),
+            "g" => (0..NUM_REGISTERS)
+                .map(|n| encode_register(n, self.get_register(n)))
+                .collect(),
+            "G" => self.write_registers(args),
+            "p" => match parse_hex(args).map(|n| n as usize) {
+                Some(n) if n < NUM_REGISTERS => encode_register(n, self.get_register(n)),
+                _ => error_packet(),
+            },
+            "P" => self.write_register(args),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "s" | "c" => {
+                if let Some(pc) = parse_hex(args) {
+                    self.emulator.chip8_vm.set_pc(pc as u16);
+                }
+                if command == "s" {
+                    stop_reply(&self.debugger.step(self.emulator))
+                } else {
+                    self.resume()?
+                }
+            }
+            "Z" | "z" => self.set_point(command == "Z", args),
+            "H" => "OK".to_string(),
+            "D" => {
+                self.send_packet("OK")?;
+                return Ok(None);
+            }
+            "k" => return Ok(None),
+            "q" => self.query(args),
+            _ => String::new(),
+        };
+        Ok(Some(reply))
+    }
+
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            "PacketSize=1000;qXfer:features:read+".to_string()
+        } else if query == "Attached" {
+            "1".to_string()
+        } else if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let Some((offset, length)) = parse_range(range) else {
+                return error_packet();
+            };
+            let xml = target_xml();
+            let start = offset.min(xml.len());
+            let end = (start + length).min(xml.len());
+            let more = if end < xml.len() { "m" } else { "l" };
+            format!("{}{}", more, &xml[start..end])
+        } else {
+            String::new()
+        }
+    }
+
+    /// Continues until the debugger stops or the client interrupts, a frame at a time
+    /// so the socket is checked in between.
+    fn resume(&mut self) -> io::Result<String> {
+        self.stream.set_nonblocking(true)?;
+        let reply = loop {
+            let frame = self.emulator.get_frame_count() + 1;
+            match self.debugger.run_until_frame(self.emulator, frame) {
+                StopReason::Frame { .. } => {}
+                reason => break Ok(stop_reply(&reason)),
+            }
+            match self.poll_interrupt() {
+                Ok(true) => break Ok(stop_packet(SIGINT)),
+                Ok(false) => {}
+                Err(error) => break Err(error),
+            }
+        };
+        self.stream.set_nonblocking(false)?;
+        reply
+    }
+
+    fn poll_interrupt(&mut self) -> io::Result<bool> {
+        let mut buffer = [0; 256];
+        match self.stream.read(&mut buffer) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(count) => self.input.extend(&buffer[..count]),
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => {}
+            Err(error) => return Err(error),
+        }
+        // it may have come in along with the packet that started the run
+        let interrupt = self.input.iter().position(|byte| *byte == INTERRUPT);
+        Ok(interrupt.and_then(|index| self.input.remove(index)).is_some())
+    }
+
+    fn get_register(&self, n: usize) -> u16 {
+        let vm = &self.emulator.chip8_vm;
+        match n {
+            REGISTER_I => vm.get_i(),
+            REGISTER_PC => vm.get_pc(),
+            REGISTER_SP => vm.get_call_stack().len() as u16,
+            REGISTER_DT => vm.timers.get_delay_timer() as u16,
+            REGISTER_ST => vm.timers.get_sound_timer() as u16,
+            x => vm.get_v()[x] as u16,
+        }
+    }
+
+    fn set_register(&mut self, n: usize, value: u16) -> bool {
+        let vm = &mut self.emulator.chip8_vm;
+        match n {
+            REGISTER_I => vm.set_i(value),
+            REGISTER_PC => vm.set_pc(value),
+            // accepted as long as it doesn't change, since gdb writes back all registers
+            REGISTER_SP => return value == vm.get_call_stack().len() as u16,
+            REGISTER_DT => vm.timers.set_delay_timer(value as u8),
+            REGISTER_ST => vm.timers.set_sound_timer(value as u8),
+            x if x < NUM_REGISTERS => vm.set_v(x, value as u8),
+            _ => return false,
+        }
+        true
+    }
+
+    fn write_registers(&mut self, hex: &str) -> String {
+        let Some(bytes) = decode_hex(hex) else {
+            return error_packet();
+        };
+        let mut offset = 0;
+        let mut ok = true;
+        for n in 0..NUM_REGISTERS {
+            let size = register_size(n);
+            let Some(value) = bytes.get(offset..offset + size) else {
+                return error_packet();
+            };
+            ok &= self.set_register(n, decode_register(value));
+            offset += size;
+        }
+        if ok {
+            "OK".to_string()
+        } else {
+            error_packet()
+        }
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let written = args.split_once('=').and_then(|(n, value)| {
+            let n = parse_hex(n)? as usize;
+            let value = decode_hex(value).filter(|value| n < NUM_REGISTERS && value.len() == register_size(n))?;
+            Some(self.set_register(n, decode_register(&value)))
+        });
+        match written {
+            Some(true) => "OK".to_string(),
+            _ => error_packet(),
+        }
+    }
+
+    fn read_memory(&self, args: &str) -> String {
+        let ram = self.emulator.chip8_vm.get_ram();
+        match parse_range(args) {
+            Some((address, length)) if address < ram.len() => {
+                encode_hex(&ram[address..(address + length).min(ram.len())])
+            }
+            _ => error_packet(),
+        }
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let Some((range, data)) = args.split_once(':') else {
+            return error_packet();
+        };
+        let ram = self.emulator.chip8_vm.get_ram_mut();
+        match (parse_range(range), decode_hex(data)) {
+            (Some((address, length)), Some(data))
+                if data.len() == length && address + length <= ram.len() =>
+            {
+                ram[address..address + length].copy_from_slice(&data);
+                "OK".to_string()
+            }
+            _ => error_packet(),
+        }
+    }
+
+    /// `Z`/`z` packets: breakpoints of either kind are the same thing here, and
+    /// watchpoints go to the debugger as given.
+    fn set_point(&mut self, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let (Some(kind), Some(address), Some(length)) = (
+            fields.next(),
+            fields.next().and_then(parse_hex),
+            fields.next().and_then(parse_hex),
+        ) else {
+            return error_packet();
+        };
+        let address = address as usize;
+        let watch = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.debugger.add_breakpoint(address as u16);
+                } else {
+                    self.debugger.remove_breakpoint(address as u16);
+                }
+                return "OK".to_string();
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::ReadWrite,
+            _ => return String::new(),
+        };
+        let range = address..address + (length as usize).max(1);
+        if insert {
+            self.debugger.add_memory_watchpoint(range, watch);
+        } else {
+            self.debugger.remove_memory_watchpoint(range);
+        }
+        "OK".to_string()
+    }
+
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.input.is_empty() {
+            let mut buffer = [0; 1024];
+            let count = self.stream.read(&mut buffer)?;
+            self.input.extend(&buffer[..count]);
+        }
+        Ok(self.input.pop_front())
+    }
+
+    /// The next packet's data, acknowledging it, or `None` once the client disconnects.
+    /// Acknowledgements and stray interrupts in between are skipped.
+    fn read_packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            match self.next_byte()? {
+                Some(b'$') => {}
+                Some(_) => continue,
+                None => return Ok(None),
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.next_byte()? {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None),
+                }
+            }
+            let mut checksum = [0; 2];
+            for digit in &mut checksum {
+                match self.next_byte()? {
+                    Some(byte) => *digit = byte,
+                    None => return Ok(None),
+                }
+            }
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            if expected == Some(checksum_of(&data)) {
+                self.stream.write_all(b"+")?;
+                return Ok(Some(data));
+            }
+            self.stream.write_all(b"-")?;
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes())
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn stop_packet(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+fn error_packet() -> String {
+    "E01".to_string()
+}
+
+fn stop_reply(reason: &StopReason) -> String {
+    match reason {
+        StopReason::MemoryWatchpoint { access, .. } => {
+            let kind = match access.kind {
+                AccessKind::Read => "rwatch",
+                AccessKind::Write => "watch",
+            };
+            format!("T{:02x}{}:{:x};", SIGTRAP, kind, access.address)
+        }
+        StopReason::Fault(VmError::UnknownOpcode { .. }) => stop_packet(SIGILL),
+        StopReason::Fault(_) => stop_packet(SIGSEGV),
+        _ => stop_packet(SIGTRAP),
+    }
+}
+
+fn register_size(n: usize) -> usize {
+    match n {
+        REGISTER_I | REGISTER_PC => 2,
+        _ => 1,
+    }
+}
+
+fn register_name(n: usize) -> String {
+    match n {
+        REGISTER_I => "i".to_string(),
+        REGISTER_PC => "pc".to_string(),
+        REGISTER_SP => "sp".to_string(),
+        REGISTER_DT => "dt".to_string(),
+        REGISTER_ST => "st".to_string(),
+        x => format!("v{:x}", x),
+    }
+}
+
+/// Registers go over the wire in target byte order, which gdb takes to be little endian.
+fn encode_register(n: usize, value: u16) -> String {
+    encode_hex(&value.to_le_bytes()[..register_size(n)])
+}
+
+fn decode_register(bytes: &[u8]) -> u16 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |value, byte| (value << 8) | *byte as u16)
+}
+
+fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n<feature name=\"org.chip8.core\">\n",
+    );
+    for n in 0..NUM_REGISTERS {
+        let bits = register_size(n) * 8;
+        let kind = match n {
+            REGISTER_PC => "code_ptr".to_string(),
+            REGISTER_I => "data_ptr".to_string(),
+            _ => format!("uint{}", bits),
+        };
+        xml.push_str(&format!(
+            "<reg name=\"{}\" bitsize=\"{}\" type=\"{}\"/>\n",
+            register_name(n),
+            bits,
+            kind
+        ));
+    }
+    xml.push_str("</feature>\n</target>\n");
+    xml
+}
+
+fn parse_hex(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+/// `ADDR,LENGTH` in hex.
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    let (address, length) = text.split_once(',')?;
+    Some((parse_hex(address)? as usize, parse_hex(length)? as usize))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&text[index..index + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8_vm::quirks::Quirks;
+    use crate::Rom;
+    use std::thread;
+
+    /// ```text
+    /// 200: v0 := 5
+    /// 202: i := 300
+    /// 204: save v0
+    /// 206: v0 += 1
+    /// 208: jump 206
+    /// ```
+    const ROM: [u8; 10] = [0x60, 0x05, 0xA3, 0x00, 0xF0, 0x55, 0x70, 0x01, 0x12, 0x06];
+
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+        fn send(&mut self, data: &str) {
+            let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+            self.stream.write_all(packet.as_bytes()).unwrap();
+        }
+
+        fn reply(&mut self) -> String {
+            let mut packet = Vec::new();
+            let mut byte = [0];
+            loop {
+                self.stream.read_exact(&mut byte).unwrap();
+                match byte[0] {
+                    b'+' if packet.is_empty() => {}
+                    b'#' => break,
+                    other => packet.push(other),
+                }
+            }
+            let mut checksum = [0; 2];
+            self.stream.read_exact(&mut checksum).unwrap();
+            self.stream.write_all(b"+").unwrap();
+            assert_eq!(packet.remove(0), b'$');
+            let data = String::from_utf8(packet).unwrap();
+            let checksum = u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap();
+            assert_eq!(checksum, checksum_of(data.as_bytes()));
+            data
+        }
+
+        fn request(&mut self, data: &str) -> String {
+            self.send(data);
+            self.reply()
+        }
+    }
+
+    #[test]
+    fn test_scripted_session() {
+        let server = GdbServer::bind(0).unwrap();
+        let address = server.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut emulator = Emulator::new(Quirks::default());
+            emulator
+                .insert_rom(Rom::from_bytes("gdb".to_string(), ROM.to_vec()))
+                .unwrap();
+            let mut debugger = Debugger::new();
+            server.serve(&mut emulator, &mut debugger).unwrap();
+            emulator.chip8_vm.get_v()[0]
+        });
+        let mut client = Client {
+            stream: TcpStream::connect(address).unwrap(),
+        };
+
+        assert!(client.request("qSupported:swbreak+").contains("qXfer:features:read+"));
+        let xml = client.request("qXfer:features:read:target.xml:0,1000");
+        assert!(xml.starts_with('l') && xml.contains("name=\"pc\""));
+        assert_eq!(client.request("?"), "S05");
+        assert_eq!(client.request("p11"), "0002");
+        assert_eq!(client.request("m200,4"), "6005a300");
+
+        // the save stops at the write watchpoint, then the breakpoint is hit
+        assert_eq!(client.request("Z2,300,1"), "OK");
+        assert_eq!(client.request("Z0,206,2"), "OK");
+        assert_eq!(client.request("c"), "T05watch:300;");
+        assert_eq!(client.request("m300,1"), "05");
+        assert_eq!(client.request("p11"), "0602");
+        assert_eq!(client.request("z2,300,1"), "OK");
+        assert_eq!(client.request("Z0,208,2"), "OK");
+        assert_eq!(client.request("c"), "S05");
+        assert_eq!(client.request("p11"), "0802");
+        assert_eq!(client.request("s"), "S05");
+        assert_eq!(client.request("p11"), "0602");
+
+        let registers = client.request("g");
+        assert_eq!(registers.len(), (16 + 2 + 2 + 3) * 2);
+        assert!(registers.starts_with("06"));
+        assert!(registers.contains("01030602"), "{}", registers);
+
+        // runs until interrupted
+        assert_eq!(client.request("z0,206,2"), "OK");
+        assert_eq!(client.request("z0,208,2"), "OK");
+        client.send("c");
+        thread::sleep(std::time::Duration::from_millis(20));
+        client.stream.write_all(&[INTERRUPT]).unwrap();
+        assert_eq!(client.reply(), "S02");
+
+        assert_eq!(client.request("P0=2a"), "OK");
+        assert_eq!(client.request("P12=05"), "E01");
+        assert_eq!(client.request("M300,2:beef"), "OK");
+        assert_eq!(client.request("m300,2"), "beef");
+        assert_eq!(client.request("vMustReplyEmpty"), "");
+        assert_eq!(client.request("\u{fffd}?"), "");
+        assert_eq!(client.request("D"), "OK");
+        assert_eq!(handle.join().unwrap(), 0x2a);
+    }
+}
@@ -1,65 +1,432 @@
+use std::fmt;
+use std::io;
 use std::path::PathBuf;
+use std::time::Duration;
 use std::vec;
 
-use crate::chip8_vm::vm::Chip8VM;
+use crate::assembler::AssembleError;
+use crate::chip8_vm::display::{Palette, DEFAULT_PALETTE};
+use crate::chip8_vm::error::VmError;
+use crate::chip8_vm::instruction::Instruction;
+use crate::chip8_vm::quirks::{Platform, Quirks};
+use crate::chip8_vm::savestate::{SaveStateError, StateReader, StateWriter, ROM_HASH_SIZE};
+use crate::chip8_vm::timing::{vip_cycles, Timing, VIP_CYCLES_PER_FRAME, VIP_INTERRUPT_CYCLES};
+use crate::chip8_vm::vm::{Chip8VM, StepInfo};
+use crate::database::{RomDatabase, RomInfo, RomOverrides};
+use crate::rewind::{RewindBuffer, DEFAULT_REWIND_BYTES, DEFAULT_REWIND_FRAMES};
+use crate::trace::Tracer;
+pub mod assembler;
 pub mod chip8_vm;
+pub mod dap;
+pub mod database;
+pub mod debugger;
+pub mod disassembler;
+pub mod gdb;
+pub mod movie;
+pub mod rewind;
+pub mod trace;
 mod resources;
 pub const MAX_FRAMES_PER_SECOND: u32 = 60;
+/// Rate of the delay and sound timers, and so of frames, in emulated time.
+pub const TIMER_FREQUENCY: u32 = 60;
+/// 20 instructions per frame.
+pub const DEFAULT_INSTRUCTIONS_PER_SECOND: u32 = 1200;
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+#[derive(Debug)]
+pub enum RomError {
+    Io(std::io::Error),
+    Assemble(AssembleError),
+    Empty,
+    TooLarge { size: usize, max_size: usize },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::Io(error) => write!(f, "could not read ROM: {}", error),
+            RomError::Assemble(error) => write!(f, "could not assemble ROM: {}", error),
+            RomError::Empty => write!(f, "ROM is empty"),
+            RomError::TooLarge { size, max_size } => write!(
+                f,
+                "ROM too large: {} bytes, only {} fit in memory",
+                size, max_size
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+impl From<std::io::Error> for RomError {
+    fn from(error: std::io::Error) -> Self {
+        RomError::Io(error)
+    }
+}
+
+impl From<AssembleError> for RomError {
+    fn from(error: AssembleError) -> Self {
+        RomError::Assemble(error)
+    }
+}
 
 pub struct Rom {
     title: String,
-    #[allow(dead_code)]
-    path: String,
-    size: u16,
+    path: Option<PathBuf>,
     data: Vec<u8>,
+    hash: [u8; ROM_HASH_SIZE],
 }
 
 impl Rom {
-    pub fn new(path: PathBuf) -> Self {
-        let rom_size = std::fs::metadata(path.clone()).unwrap().len() as u16;
-        let rom_data = std::fs::read(path.clone()).unwrap();
+    pub fn from_bytes(title: String, data: Vec<u8>) -> Self {
         Rom {
-            title: path.file_name().unwrap().to_str().unwrap().to_string(),
-            path: path.to_str().unwrap().to_string(),
-            size: rom_size,
-            data: rom_data,
+            title,
+            path: None,
+            hash: sha1_smol::Sha1::from(&data).digest().bytes(),
+            data,
         }
     }
 
+    /// Reads a ROM image, assembling it first if it's Octo source (`.8o`).
+    pub fn from_path(path: PathBuf) -> Result<Self, RomError> {
+        let data = if path.extension().is_some_and(|extension| extension == "8o") {
+            let source = std::fs::read_to_string(&path)?;
+            assembler::assemble(&source)?.bytes
+        } else {
+            std::fs::read(&path)?
+        };
+        let title = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Ok(Rom {
+            title,
+            path: Some(path),
+            hash: sha1_smol::Sha1::from(&data).digest().bytes(),
+            data,
+        })
+    }
+
     pub fn get_title(&self) -> String {
         self.title.to_string()
     }
+
+    pub fn get_path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
+
+    pub fn get_data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// SHA-1 of the ROM image.
+    pub fn get_hash(&self) -> [u8; ROM_HASH_SIZE] {
+        self.hash
+    }
+
+    /// Checks that the ROM fits in `max_size` bytes of program space.
+    pub fn validate(&self, max_size: usize) -> Result<(), RomError> {
+        if self.data.is_empty() {
+            return Err(RomError::Empty);
+        }
+        if self.data.len() > max_size {
+            return Err(RomError::TooLarge {
+                size: self.data.len(),
+                max_size,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The settings a ROM runs with, wherever they came from.
+#[derive(Debug, Clone, Copy)]
+struct Configuration {
+    platform: Platform,
+    quirks: Quirks,
+    instructions_per_second: u32,
+    palette: Palette,
 }
 
 pub struct Emulator {
     pub chip8_vm: Chip8VM,
     pub current_room: Rom,
+    rewind_buffer: RewindBuffer,
+    timing: Timing,
+    instructions_per_second: u32,
+    /// `TIMER_FREQUENCY` is added for every instruction and a frame ends each time
+    /// it reaches `instructions_per_second`, so timers tick at exactly 60 Hz even
+    /// when that isn't a whole number of instructions.
+    timer_phase: u32,
+    /// VIP cycles spent in the current frame, starting with what the last
+    /// instruction of the previous frame ran over its budget.
+    frame_cycles: u32,
+    /// Frames completed since the ROM started.
+    frame_count: u64,
+    /// Time passed to `run_for` that didn't add up to a whole instruction (or a
+    /// whole frame with VIP timing), in nanoseconds times the rate.
+    clock_remainder: u128,
+    database: Option<RomDatabase>,
+    /// What the setters chose, used for whatever the database and overrides leave open.
+    base: Configuration,
+    overrides: RomOverrides,
+    /// Database entry of the current ROM.
+    rom_info: Option<RomInfo>,
+    tracer: Option<Tracer>,
 }
 
 impl Emulator {
-    pub fn new() -> Self {
-        let mut chip8_vm: Chip8VM = Chip8VM::new();
+    pub fn new(quirks: Quirks) -> Self {
+        let mut chip8_vm: Chip8VM = Chip8VM::new(quirks);
         chip8_vm.init_fontset();
+        let platform = chip8_vm.get_platform();
 
         Emulator {
             chip8_vm,
-            current_room: Rom {
-                title: String::new(),
-                path: String::new(),
-                size: u16::MIN,
-                data: vec![],
+            current_room: Rom::from_bytes(String::new(), vec![]),
+            rewind_buffer: RewindBuffer::new(DEFAULT_REWIND_FRAMES, DEFAULT_REWIND_BYTES),
+            timing: Timing::default(),
+            instructions_per_second: DEFAULT_INSTRUCTIONS_PER_SECOND,
+            timer_phase: 0,
+            frame_cycles: 0,
+            frame_count: 0,
+            clock_remainder: 0,
+            database: Some(RomDatabase::bundled()),
+            base: Configuration {
+                platform,
+                quirks,
+                instructions_per_second: DEFAULT_INSTRUCTIONS_PER_SECOND,
+                palette: DEFAULT_PALETTE,
             },
+            overrides: RomOverrides::default(),
+            rom_info: None,
+            tracer: None,
         }
     }
 
-    pub fn load_rom(&mut self, path: PathBuf) {
-        self.current_room = Rom::new(path);
+    pub fn load_rom(&mut self, path: PathBuf) -> Result<(), RomError> {
+        let rom = Rom::from_path(path)?;
+        self.insert_rom(rom)
+    }
+
+    /// Resets the VM and loads `rom`, leaving the current ROM running if it doesn't fit.
+    /// ROMs found in the database get its platform, quirks, speed and colors,
+    /// except for the ones overridden by `set_overrides`. Anything neither of them
+    /// sets comes from the setters, not from the previous ROM.
+    pub fn insert_rom(&mut self, rom: Rom) -> Result<(), RomError> {
+        let info = self
+            .database
+            .as_ref()
+            .and_then(|database| database.lookup(&rom.get_hash()))
+            .cloned();
+        let platform = self
+            .overrides
+            .platform
+            .or(info.as_ref().map(|info| info.platform))
+            .unwrap_or(self.base.platform);
+        rom.validate(Chip8VM::program_space_for(platform))?;
+
+        self.chip8_vm.set_platform(platform);
+        let quirks = match (self.overrides.quirks, self.overrides.platform, &info) {
+            (Some(quirks), _, _) => quirks,
+            (None, Some(platform), _) => Quirks::from_platform(platform),
+            (None, None, Some(info)) => info.quirks,
+            (None, None, None) => self.base.quirks,
+        };
+        self.chip8_vm.set_quirks(quirks);
+        let instructions_per_second = self.overrides.instructions_per_second.or(info
+            .as_ref()
+            .and_then(|info| info.tickrate)
+            .map(|tickrate| tickrate * TIMER_FREQUENCY));
+        self.use_instructions_per_second(
+            instructions_per_second.unwrap_or(self.base.instructions_per_second),
+        );
+        self.chip8_vm.display.set_palette(
+            self.overrides
+                .palette
+                .or(info.as_ref().and_then(|info| info.palette))
+                .unwrap_or(self.base.palette),
+        );
+
         self.stop_emulation();
-        self.chip8_vm.load_rom(self.current_room.data.clone());
+        self.chip8_vm.load_rom(rom.data.clone())?;
+        self.current_room = rom;
+        self.rom_info = info;
+        Ok(())
+    }
+
+    /// The database entry of the current ROM, `None` if it isn't a known one.
+    pub fn get_rom_info(&self) -> Option<&RomInfo> {
+        self.rom_info.as_ref()
+    }
+
+    /// Replaces the database used to configure ROMs, `None` to configure them by hand.
+    pub fn set_rom_database(&mut self, database: Option<RomDatabase>) {
+        self.database = database;
+    }
+
+    /// Settings applied to every ROM loaded from now on, over the database's.
+    pub fn set_overrides(&mut self, overrides: RomOverrides) {
+        self.overrides = overrides;
+    }
+
+    pub fn get_overrides(&self) -> RomOverrides {
+        self.overrides
+    }
+
+    /// Starts the current ROM over on a freshly reset machine.
+    pub fn restart(&mut self) -> Result<(), RomError> {
+        self.stop_emulation();
+        self.chip8_vm.load_rom(self.current_room.data.clone())
+    }
+
+    /// Switches to `platform` and its preset quirks, also for ROMs loaded later.
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.base.platform = platform;
+        self.base.quirks = Quirks::from_platform(platform);
+        self.chip8_vm.set_platform(platform);
+        self.chip8_vm.set_quirks(self.base.quirks);
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.base.quirks = quirks;
+        self.chip8_vm.set_quirks(quirks);
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.base.palette = palette;
+        self.chip8_vm.display.set_palette(palette);
+    }
+
+    /// Makes CXNN deterministic: the same seed and inputs always give the same run.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.chip8_vm.set_seed(seed);
+    }
+
+    pub fn get_timing(&self) -> Timing {
+        self.timing
+    }
+
+    /// Chooses how frames are paced, see `run_frame`.
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+        self.frame_cycles = 0;
+        self.clock_remainder = 0;
+    }
+
+    pub fn get_instructions_per_second(&self) -> u32 {
+        self.instructions_per_second
+    }
+
+    /// Sets the CPU speed used with `Timing::Instructions`, at least one instruction per second.
+    pub fn set_instructions_per_second(&mut self, instructions_per_second: u32) {
+        self.base.instructions_per_second = instructions_per_second.max(1);
+        self.use_instructions_per_second(instructions_per_second);
+    }
+
+    fn use_instructions_per_second(&mut self, instructions_per_second: u32) {
+        self.instructions_per_second = instructions_per_second.max(1);
+        self.timer_phase = 0;
+        self.clock_remainder = 0;
+    }
+
+    /// Snapshots the whole machine, tied to the current ROM.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new(&self.current_room.get_hash());
+        self.chip8_vm.save_state(&mut writer);
+        let mut timing = vec![self.timing as u8];
+        timing.extend_from_slice(&self.frame_cycles.to_le_bytes());
+        timing.extend_from_slice(&self.instructions_per_second.to_le_bytes());
+        timing.extend_from_slice(&self.timer_phase.to_le_bytes());
+        timing.extend_from_slice(&self.frame_count.to_le_bytes());
+        writer.section(b"TIME", &timing);
+        writer.finish()
+    }
+
+    /// Restores a snapshot taken by `save_state` while the same ROM was loaded.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), SaveStateError> {
+        let reader = StateReader::new(state)?;
+        if reader.rom_hash() != &self.current_room.get_hash() {
+            return Err(SaveStateError::RomMismatch);
+        }
+        self.apply_state(&reader)
+    }
+
+    fn apply_state(&mut self, reader: &StateReader) -> Result<(), SaveStateError> {
+        let timing = match reader.section(b"TIME") {
+            Some(mut section) => {
+                let timing = Timing::try_from(section.u8()?)
+                    .map_err(|_| SaveStateError::Invalid("timing"))?;
+                let frame_cycles = section.u32()?;
+                let clock = if section.is_empty() {
+                    None
+                } else {
+                    let instructions_per_second = section.u32()?;
+                    let timer_phase = section.u32()?;
+                    if instructions_per_second == 0 {
+                        return Err(SaveStateError::Invalid("instructions per second"));
+                    }
+                    Some((instructions_per_second, timer_phase % instructions_per_second))
+                };
+                let frame_count = if section.is_empty() {
+                    None
+                } else {
+                    Some(section.u64()?)
+                };
+                Some((timing, frame_cycles, clock, frame_count))
+            }
+            None => None,
+        };
+        self.chip8_vm.load_state(reader)?;
+        if let Some((timing, frame_cycles, clock, frame_count)) = timing {
+            self.timing = timing;
+            self.frame_cycles = frame_cycles;
+            if let Some(frame_count) = frame_count {
+                self.frame_count = frame_count;
+            }
+            if let Some((instructions_per_second, timer_phase)) = clock {
+                self.instructions_per_second = instructions_per_second;
+                self.timer_phase = timer_phase;
+            }
+        }
+        Ok(())
+    }
+
+    /// Limits how far back `rewind` can go, in frames and bytes of snapshots.
+    pub fn set_rewind_limits(&mut self, max_frames: usize, max_bytes: usize) {
+        self.rewind_buffer.set_limits(max_frames, max_bytes);
+    }
+
+    pub fn rewind_frames_available(&self) -> usize {
+        self.rewind_buffer.frames()
+    }
+
+    /// Goes back up to `frames` frames, returning how many were actually rewound.
+    pub fn rewind(&mut self, frames: usize) -> usize {
+        let Some((snapshot, rewound)) = self.rewind_buffer.rewind(frames) else {
+            return 0;
+        };
+        let reader = StateReader::new(&snapshot).expect("rewind snapshots are valid");
+        self.apply_state(&reader)
+            .expect("rewind snapshots are valid");
+        self.chip8_vm.display.state = chip8_vm::display::DisplayState::Draw;
+        rewound
     }
 
     pub fn stop_emulation(&mut self) {
         self.chip8_vm.reset();
+        self.chip8_vm.init_fontset();
+        self.rewind_buffer.clear();
+        self.timer_phase = 0;
+        self.frame_cycles = 0;
+        self.frame_count = 0;
+        self.clock_remainder = 0;
+        // a fresh machine starts at the top of a frame
+        self.chip8_vm.signal_vblank();
     }
 
     pub fn is_draw_flag_set(&mut self) -> bool {
@@ -70,6 +437,33 @@ impl Emulator {
         self.chip8_vm.timers.get_sound_timer() > 0
     }
 
+    /// The XO-CHIP audio pattern with its playback rate in bits per second,
+    /// `None` on platforms that only have a buzzer.
+    pub fn get_audio_pattern(&self) -> Option<([u8; 16], f32)> {
+        if self.chip8_vm.get_platform() != Platform::XoChip {
+            return None;
+        }
+        Some((self.chip8_vm.get_audio_pattern(), self.chip8_vm.get_playback_rate()))
+    }
+
+    pub fn get_fault(&self) -> Option<VmError> {
+        self.chip8_vm.get_fault()
+    }
+
+    pub fn get_resolution(&self) -> (usize, usize) {
+        (self.chip8_vm.display.width(), self.chip8_vm.display.height())
+    }
+
+    /// SHA-1 of the framebuffer and RAM, to tell whether two runs ended up in the same place.
+    pub fn get_state_hash(&self) -> [u8; ROM_HASH_SIZE] {
+        let mut hasher = sha1_smol::Sha1::new();
+        for row in self.chip8_vm.display.buffer.iter() {
+            hasher.update(row);
+        }
+        hasher.update(self.chip8_vm.get_ram());
+        hasher.digest().bytes()
+    }
+
     pub fn get_color_bufer(&mut self) -> Vec<u8> {
         self.chip8_vm.display.get_color_buffer()
     }
@@ -78,15 +472,269 @@ impl Emulator {
         self.chip8_vm.keypad.set_key(key, state as u8)
     }
 
-    pub fn emulate_cycles(&mut self, number_of_cycles: u8) {
-        if self.current_room.size == 0 {
-            return;
+    /// Runs until the next 60 Hz timer tick, stopping early if the VM faults: the
+    /// instructions due in that time, or a cycle budget with `Timing::CosmacVip`.
+    /// Every completed frame is kept for `rewind`.
+    pub fn run_frame(&mut self) -> Result<(), VmError> {
+        if self.current_room.size() == 0 {
+            return Ok(());
         }
 
-        for _ in 0..number_of_cycles {
-            self.chip8_vm.cycle();
+        let frame = self.frame_count;
+        while self.frame_count == frame {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Runs as much as fits in `duration` of emulated time, carrying what's left
+    /// over to the next call. Frontends pass the real time since their last call,
+    /// capped so a stall doesn't turn into a burst of catching up.
+    pub fn run_for(&mut self, duration: Duration) -> Result<(), VmError> {
+        if self.current_room.size() == 0 {
+            return Ok(());
         }
+
+        match self.timing {
+            Timing::Instructions => {
+                self.clock_remainder += duration.as_nanos() * self.instructions_per_second as u128;
+                while self.clock_remainder >= NANOS_PER_SECOND {
+                    self.clock_remainder -= NANOS_PER_SECOND;
+                    self.step()?;
+                }
+            }
+            Timing::CosmacVip => {
+                self.clock_remainder += duration.as_nanos() * TIMER_FREQUENCY as u128;
+                while self.clock_remainder >= NANOS_PER_SECOND {
+                    self.clock_remainder -= NANOS_PER_SECOND;
+                    self.run_frame()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Frames completed since the ROM started.
+    pub fn get_frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Writes every instruction run from now on to `tracer`, replacing the current trace.
+    pub fn start_trace(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Ends the trace, returning the first error writing it.
+    pub fn stop_trace(&mut self) -> io::Result<()> {
+        match self.tracer.take() {
+            Some(tracer) => tracer.finish(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.tracer.is_some()
+    }
+
+    /// Runs one instruction, ending the frame when its time is up. With VIP timing
+    /// an instruction can overrun the frame by more than a whole frame's budget,
+    /// the frames after it then pass without running anything and return `None`.
+    pub fn step(&mut self) -> Result<Option<StepInfo>, VmError> {
+        match self.timing {
+            Timing::Instructions => {
+                let step = self.chip8_vm.cycle()?;
+                self.trace(&step);
+                self.timer_phase += TIMER_FREQUENCY;
+                if self.timer_phase >= self.instructions_per_second {
+                    self.timer_phase -= self.instructions_per_second;
+                    self.end_frame();
+                }
+                Ok(Some(step))
+            }
+            Timing::CosmacVip => {
+                // the cycles left after the display interrupt
+                let budget = VIP_CYCLES_PER_FRAME - VIP_INTERRUPT_CYCLES;
+                if self.frame_cycles >= budget {
+                    self.frame_cycles -= budget;
+                    self.end_frame();
+                    return Ok(None);
+                }
+                let step = self.chip8_vm.cycle()?;
+                self.trace(&step);
+                let pc = self.chip8_vm.get_pc();
+                if matches!(step.instruction, Instruction::Draw { .. }) && pc == step.pc {
+                    // DXYN waits for the display interrupt, idling out the frame
+                    self.frame_cycles = budget;
+                } else {
+                    self.frame_cycles += vip_cycles(&step, pc, &self.chip8_vm.get_v());
+                }
+                if self.frame_cycles >= budget {
+                    self.frame_cycles -= budget;
+                    self.end_frame();
+                }
+                Ok(Some(step))
+            }
+        }
+    }
+
+    fn trace(&mut self, step: &StepInfo) {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.record(step, &self.chip8_vm);
+        }
+    }
+
+    fn end_frame(&mut self) {
         self.chip8_vm.timers.update_timers();
+        self.chip8_vm.signal_vblank();
+        self.frame_count += 1;
+        self.rewind_buffer.push(self.save_state());
+    }
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emulator_with_rom(data: Vec<u8>) -> Emulator {
+        let mut emulator = Emulator::new(Quirks::default());
+        emulator
+            .insert_rom(Rom::from_bytes("test".to_string(), data))
+            .unwrap();
+        emulator
+    }
+
+    #[test]
+    fn test_save_and_load_state() {
+        // v0 += 1 in a loop, with random numbers in v1
+        let rom = vec![0x70, 0x01, 0xC1, 0xFF, 0x12, 0x00];
+        let mut emulator = emulator_with_rom(rom.clone());
+        emulator.set_instructions_per_second(600);
+        emulator.run_frame().unwrap();
+        let state = emulator.save_state();
+        emulator.run_frame().unwrap();
+        let expected = emulator.chip8_vm.get_v();
+
+        emulator.load_state(&state).unwrap();
+        emulator.run_frame().unwrap();
+        assert_eq!(emulator.chip8_vm.get_v(), expected);
+
+        let mut other = emulator_with_rom(vec![0x12, 0x00]);
+        assert_eq!(other.load_state(&state), Err(SaveStateError::RomMismatch));
+        assert_eq!(
+            emulator.load_state(&state[..state.len() - 1]),
+            Err(SaveStateError::Truncated)
+        );
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut emulator = emulator_with_rom(vec![0x70, 0x01, 0x12, 0x00]);
+        emulator.set_instructions_per_second(120);
+        for _ in 0..5 {
+            emulator.run_frame().unwrap();
+        }
+        assert_eq!(emulator.chip8_vm.get_v()[0], 5);
+        assert_eq!(emulator.rewind_frames_available(), 4);
+
+        assert_eq!(emulator.rewind(2), 2);
+        assert_eq!(emulator.chip8_vm.get_v()[0], 3);
+        emulator.run_frame().unwrap();
+        assert_eq!(emulator.chip8_vm.get_v()[0], 4);
+
+        assert_eq!(emulator.rewind(10), 3);
+        assert_eq!(emulator.chip8_vm.get_v()[0], 1);
+
+        emulator.stop_emulation();
+        assert_eq!(emulator.rewind(1), 0);
+    }
+
+    #[test]
+    fn test_timers_run_at_60_hz() {
+        // delay := 60, then spin
+        let rom = vec![0x60, 0x3C, 0xF0, 0x15, 0x12, 0x04];
+        for instructions_per_second in [1000, 7000] {
+            let mut emulator = emulator_with_rom(rom.clone());
+            emulator.set_instructions_per_second(instructions_per_second);
+            emulator.run_for(Duration::from_millis(250)).unwrap();
+            emulator.run_for(Duration::from_millis(250)).unwrap();
+            assert_eq!(emulator.chip8_vm.timers.get_delay_timer(), 30);
+        }
+
+        // 100 instructions per second make frames of 2, 2 and 1 instructions
+        let mut emulator = emulator_with_rom([0x70, 0x01].repeat(32));
+        emulator.set_instructions_per_second(100);
+        for _ in 0..3 {
+            emulator.run_frame().unwrap();
+        }
+        assert_eq!(emulator.chip8_vm.get_v()[0], 5);
+        assert_eq!(emulator.rewind_frames_available(), 2);
+    }
+
+    #[test]
+    fn test_database_configures_known_roms() {
+        let rom = std::fs::read("../roms/test_opcode.ch8").unwrap();
+        let mut emulator = Emulator::new(Quirks::default());
+        emulator.set_platform(Platform::XoChip);
+        emulator.set_instructions_per_second(900);
+        emulator
+            .insert_rom(Rom::from_bytes("test".to_string(), rom.clone()))
+            .unwrap();
+        assert_eq!(emulator.get_rom_info().unwrap().title, "Chip8 Test");
+        assert_eq!(emulator.chip8_vm.get_platform(), Platform::Chip8);
+        assert_eq!(emulator.get_instructions_per_second(), 1200);
+
+        emulator.set_overrides(RomOverrides {
+            platform: Some(Platform::SuperChip),
+            instructions_per_second: Some(600),
+            ..RomOverrides::default()
+        });
+        emulator
+            .insert_rom(Rom::from_bytes("test".to_string(), rom))
+            .unwrap();
+        assert_eq!(emulator.chip8_vm.get_platform(), Platform::SuperChip);
+        assert_eq!(
+            emulator.chip8_vm.get_quirks(),
+            Quirks::from_platform(Platform::SuperChip)
+        );
+        assert_eq!(emulator.get_instructions_per_second(), 600);
+
+        // unknown ROMs get the settings from before the known ones
+        emulator.set_overrides(RomOverrides::default());
+        emulator
+            .insert_rom(Rom::from_bytes("other".to_string(), vec![0x12, 0x00]))
+            .unwrap();
+        assert!(emulator.get_rom_info().is_none());
+        assert_eq!(emulator.chip8_vm.get_platform(), Platform::XoChip);
+        assert_eq!(
+            emulator.chip8_vm.get_quirks(),
+            Quirks::from_platform(Platform::XoChip)
+        );
+        assert_eq!(emulator.get_instructions_per_second(), 900);
+        assert_eq!(emulator.chip8_vm.display.palette(), DEFAULT_PALETTE);
+    }
+
+    #[test]
+    fn test_vip_timing() {
+        // v0 += 1 in a loop: 50 and 52 cycles per pass out of 2598 a frame
+        let mut emulator = emulator_with_rom(vec![0x70, 0x01, 0x12, 0x00]);
+        emulator.set_timing(Timing::CosmacVip);
+        emulator.run_frame().unwrap();
+        assert_eq!(emulator.chip8_vm.get_v()[0], 26);
+        // the 2 cycles of overrun come out of the next frame, restored with the state
+        let state = emulator.save_state();
+        emulator.run_frame().unwrap();
+        let expected = emulator.chip8_vm.get_v();
+        emulator.load_state(&state).unwrap();
+        emulator.run_frame().unwrap();
+        assert_eq!(emulator.chip8_vm.get_v(), expected);
+
+        // a sprite waiting for the display interrupt ends the frame
+        let mut emulator = emulator_with_rom(vec![0xD0, 0x05, 0x71, 0x01, 0x12, 0x00]);
+        emulator.set_quirks(Quirks::from_platform(Platform::Chip8));
+        emulator.set_timing(Timing::CosmacVip);
+        for _ in 0..3 {
+            emulator.run_frame().unwrap();
+        }
+        assert_eq!(emulator.chip8_vm.get_v()[1], 3);
     }
 }
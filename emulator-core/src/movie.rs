@@ -0,0 +1,347 @@
+use std::fmt;
+
+use crate::chip8_vm::error::VmError;
+use crate::chip8_vm::quirks::{Platform, Quirks};
+use crate::chip8_vm::savestate::{SaveStateError, Section, ROM_HASH_SIZE};
+use crate::chip8_vm::timing::Timing;
+use crate::{Emulator, RomError};
+
+/// Movie layout, all integers little endian:
+///
+/// ```text
+/// magic        "C8MV"
+/// version      u16
+/// rom hash     20 bytes, SHA-1 of the ROM
+/// platform     u8
+/// quirks       u8 count, then one byte per quirk
+/// seed         u64
+/// speed        u32, instructions per second
+/// interval     u32, frames between checkpoints
+/// frames       u32 count, then a u16 keypad bitmask per frame (bit N = key N)
+/// checkpoints  u32 count, then u32 frame and 20 byte state hash for each
+/// timing       u8, 0 instructions per frame, 1 COSMAC VIP cycles
+/// ```
+pub const MOVIE_VERSION: u16 = 1;
+const MAGIC: &[u8; 4] = b"C8MV";
+/// One second at 60 frames per second.
+pub const DEFAULT_CHECKPOINT_INTERVAL: u32 = 60;
+const NUM_KEYS: u8 = 16;
+
+#[derive(Debug)]
+pub enum MovieError {
+    NotAMovie,
+    UnsupportedVersion(u16),
+    Truncated,
+    Invalid(&'static str),
+    RomMismatch,
+    Rom(RomError),
+    /// The state after `frame` frames differs from the recording.
+    Desync { frame: u32 },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::NotAMovie => write!(f, "not a movie"),
+            MovieError::UnsupportedVersion(version) => write!(
+                f,
+                "movie version {} is newer than the supported {}",
+                version, MOVIE_VERSION
+            ),
+            MovieError::Truncated => write!(f, "movie is truncated"),
+            MovieError::Invalid(field) => write!(f, "movie has an invalid {}", field),
+            MovieError::RomMismatch => write!(f, "movie was recorded with a different ROM"),
+            MovieError::Rom(error) => write!(f, "could not restart the ROM: {}", error),
+            MovieError::Desync { frame } => write!(f, "playback desynced at frame {}", frame),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<SaveStateError> for MovieError {
+    fn from(error: SaveStateError) -> Self {
+        match error {
+            SaveStateError::Invalid(field) => MovieError::Invalid(field),
+            _ => MovieError::Truncated,
+        }
+    }
+}
+
+impl From<RomError> for MovieError {
+    fn from(error: RomError) -> Self {
+        MovieError::Rom(error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: [u8; ROM_HASH_SIZE],
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub seed: u64,
+    pub instructions_per_second: u32,
+    pub checkpoint_interval: u32,
+    pub frames: Vec<u16>,
+    /// Frames played and the state hash at that point.
+    pub checkpoints: Vec<(u32, [u8; ROM_HASH_SIZE])>,
+    pub timing: Timing,
+}
+
+impl Movie {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&MOVIE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.rom_hash);
+        bytes.push(self.platform as u8);
+        let quirks = self.quirks.to_bytes();
+        bytes.push(quirks.len() as u8);
+        bytes.extend_from_slice(&quirks);
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&self.instructions_per_second.to_le_bytes());
+        bytes.extend_from_slice(&self.checkpoint_interval.to_le_bytes());
+        bytes.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for keys in &self.frames {
+            bytes.extend_from_slice(&keys.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(self.checkpoints.len() as u32).to_le_bytes());
+        for (frame, hash) in &self.checkpoints {
+            bytes.extend_from_slice(&frame.to_le_bytes());
+            bytes.extend_from_slice(hash);
+        }
+        bytes.push(self.timing as u8);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MovieError> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(MovieError::NotAMovie);
+        }
+        let mut reader = Section::new(&bytes[MAGIC.len()..]);
+        let version = reader.u16()?;
+        if version > MOVIE_VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+        let rom_hash = reader.bytes(ROM_HASH_SIZE)?.try_into().unwrap();
+        let platform =
+            Platform::try_from(reader.u8()?).map_err(|_| MovieError::Invalid("platform"))?;
+        let quirk_count = reader.u8()? as usize;
+        let quirks =
+            Quirks::from_bytes(reader.bytes(quirk_count)?).ok_or(MovieError::Invalid("quirks"))?;
+        let seed = reader.u64()?;
+        let instructions_per_second = reader.u32()?;
+        let checkpoint_interval = reader.u32()?;
+
+        let frame_count = reader.u32()?;
+        let mut frames = Vec::new();
+        for _ in 0..frame_count {
+            frames.push(reader.u16()?);
+        }
+        let checkpoint_count = reader.u32()?;
+        let mut checkpoints = Vec::new();
+        for _ in 0..checkpoint_count {
+            let frame = reader.u32()?;
+            checkpoints.push((frame, reader.bytes(ROM_HASH_SIZE)?.try_into().unwrap()));
+        }
+        let timing = Timing::try_from(reader.u8()?).map_err(|_| MovieError::Invalid("timing"))?;
+
+        Ok(Movie {
+            rom_hash,
+            platform,
+            quirks,
+            seed,
+            instructions_per_second,
+            checkpoint_interval,
+            frames,
+            checkpoints,
+            timing,
+        })
+    }
+}
+
+fn keypad_state(emulator: &Emulator) -> u16 {
+    (0..NUM_KEYS)
+        .filter(|key| emulator.chip8_vm.keypad.get_key(*key) != 0)
+        .fold(0, |keys, key| keys | 1 << key)
+}
+
+/// Records the keypad state of every frame from a fresh start of the current ROM.
+pub struct MovieRecorder {
+    movie: Movie,
+}
+
+impl MovieRecorder {
+    /// Restarts the current ROM with the emulator's seed, speed and timing and starts recording.
+    pub fn start(emulator: &mut Emulator) -> Result<Self, RomError> {
+        emulator.restart()?;
+        Ok(MovieRecorder {
+            movie: Movie {
+                rom_hash: emulator.current_room.get_hash(),
+                platform: emulator.chip8_vm.get_platform(),
+                quirks: emulator.chip8_vm.get_quirks(),
+                seed: emulator.chip8_vm.get_seed(),
+                instructions_per_second: emulator.get_instructions_per_second(),
+                checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+                frames: Vec::new(),
+                checkpoints: Vec::new(),
+                timing: emulator.get_timing(),
+            },
+        })
+    }
+
+    /// Runs a frame with the keys currently held, as set by `Emulator::handle_input`.
+    /// Frames that fault are still recorded so playback reproduces the fault.
+    pub fn record_frame(&mut self, emulator: &mut Emulator) -> Result<(), VmError> {
+        self.movie.frames.push(keypad_state(emulator));
+        let result = emulator.run_frame();
+
+        let frame = self.movie.frames.len() as u32;
+        if frame.is_multiple_of(self.movie.checkpoint_interval) {
+            self.movie.checkpoints.push((frame, emulator.get_state_hash()));
+        }
+        result
+    }
+
+    /// Ends the recording, checkpointing the final frame.
+    pub fn finish(mut self, emulator: &Emulator) -> Movie {
+        let frame = self.movie.frames.len() as u32;
+        if self.movie.checkpoints.last().map(|(last, _)| *last) != Some(frame) {
+            self.movie.checkpoints.push((frame, emulator.get_state_hash()));
+        }
+        self.movie
+    }
+}
+
+/// Feeds a movie's inputs back into an emulator, checking its checkpoints.
+pub struct MoviePlayer {
+    movie: Movie,
+    frame: usize,
+    next_checkpoint: usize,
+}
+
+impl MoviePlayer {
+    /// Applies the movie's platform, quirks, seed, speed and timing and restarts the current ROM,
+    /// which must be the one the movie was recorded with.
+    pub fn start(movie: Movie, emulator: &mut Emulator) -> Result<Self, MovieError> {
+        if movie.rom_hash != emulator.current_room.get_hash() {
+            return Err(MovieError::RomMismatch);
+        }
+        emulator.set_platform(movie.platform);
+        emulator.set_quirks(movie.quirks);
+        emulator.set_seed(movie.seed);
+        emulator.set_instructions_per_second(movie.instructions_per_second);
+        emulator.set_timing(movie.timing);
+        emulator.restart()?;
+        Ok(MoviePlayer {
+            movie,
+            frame: 0,
+            next_checkpoint: 0,
+        })
+    }
+
+    /// Frames played so far.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.movie.frames.len()
+    }
+
+    /// Plays the next frame. VM faults are part of the recording and aren't errors,
+    /// a desync is reported at the first checkpoint that doesn't match.
+    pub fn play_frame(&mut self, emulator: &mut Emulator) -> Result<(), MovieError> {
+        let Some(&keys) = self.movie.frames.get(self.frame) else {
+            return Ok(());
+        };
+        for key in 0..NUM_KEYS {
+            emulator.handle_input(key, keys & (1 << key) != 0);
+        }
+        let _ = emulator.run_frame();
+        self.frame += 1;
+
+        let frame = self.frame as u32;
+        while let Some(&(checkpoint, hash)) = self.movie.checkpoints.get(self.next_checkpoint) {
+            if checkpoint > frame {
+                break;
+            }
+            self.next_checkpoint += 1;
+            if checkpoint == frame && hash != emulator.get_state_hash() {
+                return Err(MovieError::Desync { frame });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn play_to_end(&mut self, emulator: &mut Emulator) -> Result<(), MovieError> {
+        while !self.is_finished() {
+            self.play_frame(emulator)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Rom;
+
+    /// Moves v1 by a random amount each loop and draws a digit there while key 5 is held.
+    const ROM: [u8; 12] = [
+        0x65, 0x05, 0xC0, 0x0F, 0x81, 0x04, 0xE5, 0xA1, 0xD0, 0x15, 0x12, 0x02,
+    ];
+
+    fn emulator() -> Emulator {
+        let mut emulator = Emulator::new(Quirks::default());
+        emulator
+            .insert_rom(Rom::from_bytes("movie".to_string(), ROM.to_vec()))
+            .unwrap();
+        emulator.set_instructions_per_second(600);
+        emulator
+    }
+
+    fn record() -> Movie {
+        let mut emulator = emulator();
+        let mut recorder = MovieRecorder::start(&mut emulator).unwrap();
+        for frame in 0..150 {
+            emulator.handle_input(5, frame % 7 < 3);
+            recorder.record_frame(&mut emulator).unwrap();
+        }
+        recorder.finish(&emulator)
+    }
+
+    #[test]
+    fn test_playback_matches_recording() {
+        let movie = record();
+        assert_eq!(movie.checkpoints.len(), 3);
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+
+        // a fresh emulator with another seed replays the same run
+        let mut emulator = emulator();
+        emulator.set_seed(1234);
+        emulator.set_instructions_per_second(900);
+        let mut player = MoviePlayer::start(movie, &mut emulator).unwrap();
+        player.play_to_end(&mut emulator).unwrap();
+        assert_eq!(player.frame(), 150);
+    }
+
+    #[test]
+    fn test_reports_first_desync() {
+        let mut movie = record();
+        movie.frames[70] ^= 1 << 5;
+        let mut emulator = emulator();
+        let mut player = MoviePlayer::start(movie, &mut emulator).unwrap();
+        let error = player.play_to_end(&mut emulator).unwrap_err();
+        assert!(matches!(error, MovieError::Desync { frame: 120 }));
+
+        let movie = record();
+        let mut other = Emulator::new(Quirks::default());
+        other
+            .insert_rom(Rom::from_bytes("other".to_string(), vec![0x12, 0x00]))
+            .unwrap();
+        assert!(matches!(
+            MoviePlayer::start(movie, &mut other),
+            Err(MovieError::RomMismatch)
+        ));
+    }
+}
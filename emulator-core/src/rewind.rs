@@ -0,0 +1,210 @@
+use std::collections::VecDeque;
+
+/// Ten seconds of frames at 60 frames per second.
+pub const DEFAULT_REWIND_FRAMES: usize = 600;
+pub const DEFAULT_REWIND_BYTES: usize = 8 * 1024 * 1024;
+
+/// Marks a delta stored as the full older snapshot, used when the sizes differ.
+const FULL_SNAPSHOT: u8 = 0;
+const XOR_DELTA: u8 = 1;
+
+/// Per-frame snapshots kept within a frame count and memory budget.
+///
+/// Only the newest snapshot is stored whole. Every older one is the XOR against
+/// the snapshot after it, with runs of unchanged bytes collapsed, so walking
+/// back from the newest restores each frame in turn and dropping the oldest
+/// delta never invalidates the others.
+pub struct RewindBuffer {
+    max_frames: usize,
+    max_bytes: usize,
+    latest: Option<Vec<u8>>,
+    /// Oldest first.
+    deltas: VecDeque<Vec<u8>>,
+    delta_bytes: usize,
+}
+
+impl RewindBuffer {
+    pub fn new(max_frames: usize, max_bytes: usize) -> Self {
+        RewindBuffer {
+            max_frames,
+            max_bytes,
+            latest: None,
+            deltas: VecDeque::new(),
+            delta_bytes: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.delta_bytes = 0;
+    }
+
+    /// Number of frames that can be rewound.
+    pub fn frames(&self) -> usize {
+        self.deltas.len()
+    }
+
+    /// Memory used by the snapshots, in bytes.
+    pub fn size(&self) -> usize {
+        self.delta_bytes + self.latest.as_ref().map_or(0, Vec::len)
+    }
+
+    pub fn set_limits(&mut self, max_frames: usize, max_bytes: usize) {
+        self.max_frames = max_frames;
+        self.max_bytes = max_bytes;
+        self.evict();
+    }
+
+    pub fn push(&mut self, snapshot: Vec<u8>) {
+        if let Some(previous) = self.latest.take() {
+            let delta = encode_delta(&snapshot, &previous);
+            self.delta_bytes += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.latest = Some(snapshot);
+        self.evict();
+    }
+
+    /// Steps back up to `frames` snapshots, returning the one reached and how
+    /// many frames it is behind the newest. `None` if there's nothing to rewind.
+    pub fn rewind(&mut self, frames: usize) -> Option<(Vec<u8>, usize)> {
+        let mut snapshot = self.latest.take()?;
+        let mut rewound = 0;
+        while rewound < frames {
+            let Some(delta) = self.deltas.pop_back() else {
+                break;
+            };
+            self.delta_bytes -= delta.len();
+            snapshot = decode_delta(&snapshot, &delta);
+            rewound += 1;
+        }
+        self.latest = Some(snapshot.clone());
+        Some((snapshot, rewound))
+    }
+
+    fn evict(&mut self) {
+        while self.deltas.len() > self.max_frames || self.size() > self.max_bytes {
+            match self.deltas.pop_front() {
+                Some(delta) => self.delta_bytes -= delta.len(),
+                None => break,
+            }
+        }
+    }
+}
+
+fn push_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], position: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*position];
+        *position += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+/// Encodes `older` relative to `newer` as pairs of (unchanged run, changed bytes).
+fn encode_delta(newer: &[u8], older: &[u8]) -> Vec<u8> {
+    if newer.len() != older.len() {
+        let mut delta = vec![FULL_SNAPSHOT];
+        delta.extend_from_slice(older);
+        return delta;
+    }
+
+    let mut delta = vec![XOR_DELTA];
+    let mut position = 0;
+    while position < newer.len() {
+        let start = position;
+        while position < newer.len() && newer[position] == older[position] {
+            position += 1;
+        }
+        let unchanged = position - start;
+        let start = position;
+        while position < newer.len() && newer[position] != older[position] {
+            position += 1;
+        }
+        push_varint(&mut delta, unchanged);
+        push_varint(&mut delta, position - start);
+        delta.extend((start..position).map(|i| newer[i] ^ older[i]));
+    }
+    delta
+}
+
+fn decode_delta(newer: &[u8], delta: &[u8]) -> Vec<u8> {
+    if delta[0] == FULL_SNAPSHOT {
+        return delta[1..].to_vec();
+    }
+
+    let mut older = newer.to_vec();
+    let mut position = 1;
+    let mut offset = 0;
+    while position < delta.len() {
+        offset += read_varint(delta, &mut position);
+        let changed = read_varint(delta, &mut position);
+        for byte in &mut older[offset..offset + changed] {
+            *byte ^= delta[position];
+            position += 1;
+        }
+        offset += changed;
+    }
+    older
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(value: u8) -> Vec<u8> {
+        let mut snapshot = vec![0; 4096];
+        snapshot[100] = value;
+        snapshot[4000] = value.wrapping_mul(3);
+        snapshot
+    }
+
+    #[test]
+    fn test_rewind_restores_each_frame() {
+        let mut buffer = RewindBuffer::new(DEFAULT_REWIND_FRAMES, DEFAULT_REWIND_BYTES);
+        for value in 0..10 {
+            buffer.push(frame(value));
+        }
+        assert_eq!(buffer.frames(), 9);
+        // deltas only hold the changed bytes
+        assert!(buffer.size() < 4096 + 9 * 16);
+
+        assert_eq!(buffer.rewind(1), Some((frame(8), 1)));
+        assert_eq!(buffer.rewind(3), Some((frame(5), 3)));
+        assert_eq!(buffer.rewind(100), Some((frame(0), 5)));
+        assert_eq!(buffer.rewind(1), Some((frame(0), 0)));
+    }
+
+    #[test]
+    fn test_limits_drop_the_oldest_frames() {
+        let mut buffer = RewindBuffer::new(3, DEFAULT_REWIND_BYTES);
+        for value in 0..10 {
+            buffer.push(frame(value));
+        }
+        assert_eq!(buffer.frames(), 3);
+        assert_eq!(buffer.rewind(10), Some((frame(6), 3)));
+
+        // snapshots of different sizes, as after switching to XO-CHIP
+        let mut buffer = RewindBuffer::new(10, 4096 + 20);
+        buffer.push(vec![1; 16]);
+        buffer.push(frame(1));
+        assert_eq!(buffer.frames(), 1);
+        buffer.push(frame(2));
+        assert_eq!(buffer.frames(), 1);
+        assert_eq!(buffer.rewind(1), Some((frame(1), 1)));
+    }
+}
@@ -0,0 +1,197 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use crate::chip8_vm::vm::{Chip8VM, StepInfo};
+
+/// Text traces have one line per instruction, registers as they are after it ran:
+///
+/// ```text
+///      cycle pc   op   v0 v1 v2 v3 v4 v5 v6 v7 v8 v9 va vb vc vd ve vf i    mnemonic
+///          1 0200 6005 05 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 0000 v0 := 0x05
+/// ```
+///
+/// The cycle counts every instruction run since the trace started, traced or
+/// not, in decimal; everything else is hex. Binary traces start with a header
+/// and then have a fixed size record per instruction, integers little endian:
+///
+/// ```text
+/// magic    "C8TR"
+/// version  u16
+/// records  u64 cycle, u16 pc, u16 opcode, 16 bytes v0-vf, u16 i
+/// ```
+pub const TRACE_VERSION: u16 = 1;
+const MAGIC: &[u8; 4] = b"C8TR";
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum TraceFormat {
+    Text,
+    Binary,
+}
+
+/// Writes a record for every instruction, see `Emulator::start_trace`.
+pub struct Tracer {
+    writer: Box<dyn Write>,
+    format: TraceFormat,
+    pc_range: RangeInclusive<u16>,
+    cycle: u64,
+    /// The first write error, after which nothing more is written.
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub fn new(writer: impl Write + 'static, format: TraceFormat) -> Self {
+        let mut tracer = Tracer {
+            writer: Box::new(writer),
+            format,
+            pc_range: 0..=u16::MAX,
+            cycle: 0,
+            error: None,
+        };
+        if format == TraceFormat::Binary {
+            let mut header = MAGIC.to_vec();
+            header.extend_from_slice(&TRACE_VERSION.to_le_bytes());
+            tracer.write(&header);
+        }
+        tracer
+    }
+
+    /// Traces to a new file at `path`, replacing any file already there.
+    pub fn create(path: &Path, format: TraceFormat) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?), format))
+    }
+
+    pub fn stdout(format: TraceFormat) -> Self {
+        Self::new(BufWriter::new(io::stdout()), format)
+    }
+
+    /// Only instructions at these addresses are written, to keep traces of long runs small.
+    pub fn set_pc_range(&mut self, pc_range: RangeInclusive<u16>) {
+        self.pc_range = pc_range;
+    }
+
+    pub(crate) fn record(&mut self, step: &StepInfo, vm: &Chip8VM) {
+        self.cycle += 1;
+        if !self.pc_range.contains(&step.pc) || self.error.is_some() {
+            return;
+        }
+        let v = vm.get_v();
+        let i = vm.get_i();
+        match self.format {
+            TraceFormat::Text => {
+                let registers: Vec<String> = v.iter().map(|value| format!("{:02X}", value)).collect();
+                let line = format!(
+                    "{:>10} {:04X} {:04X} {} {:04X} {}\n",
+                    self.cycle,
+                    step.pc,
+                    step.opcode,
+                    registers.join(" "),
+                    i,
+                    step.instruction
+                );
+                self.write(line.as_bytes());
+            }
+            TraceFormat::Binary => {
+                let mut record = Vec::with_capacity(30);
+                record.extend_from_slice(&self.cycle.to_le_bytes());
+                record.extend_from_slice(&step.pc.to_le_bytes());
+                record.extend_from_slice(&step.opcode.to_le_bytes());
+                record.extend_from_slice(&v);
+                record.extend_from_slice(&i.to_le_bytes());
+                self.write(&record);
+            }
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        if let Err(error) = self.writer.write_all(bytes) {
+            self.error = Some(error);
+        }
+    }
+
+    /// Flushes the trace, returning the first error it ran into.
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => self.writer.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8_vm::quirks::Quirks;
+    use crate::{Emulator, Rom};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// A writer whose output the test can still read once the tracer owns it.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run_traced(tracer: Tracer) -> Emulator {
+        // v0 := 5, i := 0x300, then v1 += 1 in a loop
+        let rom = vec![0x60, 0x05, 0xA3, 0x00, 0x71, 0x01, 0x12, 0x04];
+        let mut emulator = Emulator::new(Quirks::default());
+        emulator
+            .insert_rom(Rom::from_bytes("test".to_string(), rom))
+            .unwrap();
+        emulator.start_trace(tracer);
+        for _ in 0..6 {
+            emulator.step().unwrap();
+        }
+        emulator
+    }
+
+    #[test]
+    fn test_text_trace() {
+        let buffer = SharedBuffer::default();
+        let mut emulator = run_traced(Tracer::new(buffer.clone(), TraceFormat::Text));
+        assert!(emulator.is_tracing());
+        emulator.stop_trace().unwrap();
+        assert!(!emulator.is_tracing());
+
+        let text = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(
+            lines[0],
+            "         1 0200 6005 05 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 0000 v0 := 0x05"
+        );
+        assert_eq!(
+            lines[4],
+            "         5 0204 7101 05 02 00 00 00 00 00 00 00 00 00 00 00 00 00 00 0300 v1 += 0x01"
+        );
+    }
+
+    #[test]
+    fn test_binary_trace_with_pc_range() {
+        let buffer = SharedBuffer::default();
+        let mut tracer = Tracer::new(buffer.clone(), TraceFormat::Binary);
+        tracer.set_pc_range(0x204..=0x205);
+        run_traced(tracer).stop_trace().unwrap();
+
+        let bytes = buffer.0.borrow();
+        assert_eq!(&bytes[..6], b"C8TR\x01\x00");
+        // both passes through the increment, the other instructions still counted
+        let records: Vec<&[u8]> = bytes[6..].chunks(30).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1][..8], 5u64.to_le_bytes());
+        assert_eq!(records[1][8..12], [0x04, 0x02, 0x01, 0x71]);
+        assert_eq!(records[1][12..16], [0x05, 0x02, 0x00, 0x00]);
+        assert_eq!(records[1][28..], [0x00, 0x03]);
+    }
+}
@@ -0,0 +1,143 @@
+//! Runs test ROMs headless under every platform profile and compares the final
+//! display against the golden images in `tests/golden`, one text file per ROM and
+//! platform with a character per pixel: `.` off, `#` first plane, `+` second plane
+//! and `*` both.
+//!
+//! Run with `UPDATE_GOLDEN=1` to rewrite the golden images after an intended change,
+//! then review the diff.
+//!
+//! The corax+, flags and quirks ROMs from Timendus' test suite aren't checked in.
+//! Point `CHIP8_TEST_SUITES` at a directory holding them under their release names
+//! to run them too, against the golden images in `tests/golden/suites`; record
+//! those with `UPDATE_GOLDEN=1` the first time and check the results on screen.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use emulator_core::chip8_vm::quirks::{Platform, Quirks};
+use emulator_core::database::RomOverrides;
+use emulator_core::Emulator;
+
+const FRAMES: usize = 300;
+/// The suites draw their results more slowly than our own ROMs.
+const SUITE_FRAMES: usize = 600;
+const INSTRUCTIONS_PER_SECOND: u32 = 1200;
+const SEED: u64 = 0;
+const PIXELS: [char; 4] = ['.', '#', '+', '*'];
+
+/// Name, platform and the value the quirks suite reads from 0x1FF to skip its menu.
+const PLATFORMS: [(&str, Platform, u8); 4] = [
+    ("chip8", Platform::Chip8, 1),
+    ("chip48", Platform::Chip48, 2),
+    ("schip", Platform::SuperChip, 2),
+    ("xochip", Platform::XoChip, 3),
+];
+const SUITES_DIRECTORY: &str = "CHIP8_TEST_SUITES";
+const SUITES: [(&str, &str); 3] = [
+    ("corax+", "3-corax+.ch8"),
+    ("flags", "4-flags.ch8"),
+    ("quirks", "5-quirks.ch8"),
+];
+const SUITE_PLATFORM_ADDRESS: usize = 0x1FF;
+
+fn manifest_path(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(path)
+}
+
+fn run(rom: &Path, platform: Platform, suite_platform: Option<u8>, frames: usize) -> String {
+    let mut emulator = Emulator::new(Quirks::from_platform(platform));
+    // the ROM database would pick the platform and speed for known ROMs
+    emulator.set_overrides(RomOverrides {
+        platform: Some(platform),
+        instructions_per_second: Some(INSTRUCTIONS_PER_SECOND),
+        ..RomOverrides::default()
+    });
+    emulator.set_seed(SEED);
+    emulator
+        .load_rom(rom.to_path_buf())
+        .unwrap_or_else(|error| panic!("{}: {}", rom.display(), error));
+    if let Some(value) = suite_platform {
+        emulator.chip8_vm.get_ram_mut()[SUITE_PLATFORM_ADDRESS] = value;
+    }
+    for frame in 0..frames {
+        if let Err(error) = emulator.run_frame() {
+            panic!("{} faulted at frame {}: {}", rom.display(), frame, error);
+        }
+    }
+
+    let (width, height) = emulator.get_resolution();
+    let mut image = String::new();
+    for row in &emulator.chip8_vm.display.buffer[..height] {
+        image.extend(row[..width].iter().map(|pixel| PIXELS[*pixel as usize & 3]));
+        image.push('\n');
+    }
+    image
+}
+
+/// Checks `rom` under every platform against the images named after `name` in
+/// `golden`, returning every mismatch.
+fn compare(rom: &Path, name: &str, golden: &Path, suite: bool) -> Vec<String> {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let frames = if suite { SUITE_FRAMES } else { FRAMES };
+
+    let mut mismatches = Vec::new();
+    for (platform_name, platform, suite_platform) in PLATFORMS {
+        let image = run(rom, platform, suite.then_some(suite_platform), frames);
+        let golden = golden.join(format!("{}-{}.txt", name, platform_name));
+        if update {
+            fs::create_dir_all(golden.parent().unwrap()).unwrap();
+            fs::write(&golden, &image).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&golden) {
+            Ok(expected) if expected == image => {}
+            Ok(_) => mismatches.push(format!("{} under {}, got:\n{}", name, platform_name, image)),
+            Err(error) => mismatches.push(format!("{}: {}", golden.display(), error)),
+        }
+    }
+    mismatches
+}
+
+fn check(rom: &str) {
+    let rom = manifest_path(rom);
+    let name = rom.file_stem().unwrap().to_string_lossy().into_owned();
+    let mismatches = compare(&rom, &name, &manifest_path("tests/golden"), false);
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
+
+#[test]
+fn test_opcode() {
+    check("../roms/test_opcode.ch8");
+}
+
+#[test]
+fn test_flags() {
+    check("tests/roms/flags.8o");
+}
+
+#[test]
+fn test_quirks() {
+    check("tests/roms/quirks.8o");
+}
+
+#[test]
+fn test_particles() {
+    check("../roms/particles.ch8");
+}
+
+#[test]
+fn test_suites() {
+    let Some(directory) = std::env::var_os(SUITES_DIRECTORY).map(PathBuf::from) else {
+        return;
+    };
+    let mut mismatches = Vec::new();
+    for (name, file) in SUITES {
+        let rom = directory.join(file);
+        if !rom.exists() {
+            eprintln!("{} not found, skipping it", rom.display());
+            continue;
+        }
+        mismatches.extend(compare(&rom, name, &manifest_path("tests/golden/suites"), true));
+    }
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
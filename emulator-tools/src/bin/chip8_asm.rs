@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use emulator_core::assembler::assemble;
+
+const USAGE: &str = "usage: chip8-asm <source.8o> [-o <rom.ch8>]";
+
+fn main() -> ExitCode {
+    let mut input = None;
+    let mut output = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => match args.next() {
+                Some(path) => output = Some(PathBuf::from(path)),
+                None => {
+                    eprintln!("{}", USAGE);
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ => input = Some(PathBuf::from(arg)),
+        }
+    }
+
+    let Some(input) = input else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    let output = output.unwrap_or_else(|| input.with_extension("ch8"));
+
+    let source = match std::fs::read_to_string(&input) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("{}: {}", input.display(), error);
+            return ExitCode::FAILURE;
+        }
+    };
+    let program = match assemble(&source) {
+        Ok(program) => program,
+        Err(error) => {
+            eprintln!("{}:{}", input.display(), error);
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(error) = std::fs::write(&output, &program.bytes) {
+        eprintln!("{}: {}", output.display(), error);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
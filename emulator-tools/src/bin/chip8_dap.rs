@@ -0,0 +1,23 @@
+use std::process::ExitCode;
+
+use emulator_core::dap;
+
+const USAGE: &str = "usage: chip8-dap
+
+Debug Adapter Protocol server on stdin and stdout, for editors to start. The ROM
+is given by the launch request's 'program', Octo source (.8o) allows breakpoints
+by line.";
+
+fn main() -> ExitCode {
+    if std::env::args().len() > 1 {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+    match dap::serve(std::io::stdin(), std::io::stdout().lock()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("chip8-dap: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
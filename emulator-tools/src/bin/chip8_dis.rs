@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use emulator_core::chip8_vm::quirks::Platform;
+use emulator_core::chip8_vm::vm::Chip8VM;
+use emulator_core::disassembler::disassemble;
+use emulator_core::Rom;
+
+const USAGE: &str = "usage: chip8-dis [--platform chip8|chip48|schip|xochip] <rom.ch8>";
+
+fn main() -> ExitCode {
+    let mut platform = Platform::XoChip;
+    let mut path = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--platform" | "-p" => match args.next().map(|name| name.parse()) {
+                Some(Ok(parsed)) => platform = parsed,
+                Some(Err(error)) => {
+                    eprintln!("{}", error);
+                    return ExitCode::FAILURE;
+                }
+                None => {
+                    eprintln!("{}", USAGE);
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ => path = Some(PathBuf::from(arg)),
+        }
+    }
+
+    let Some(path) = path else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    let rom = match Rom::from_path(path).and_then(|rom| {
+        rom.validate(Chip8VM::program_space_for(platform))?;
+        Ok(rom)
+    }) {
+        Ok(rom) => rom,
+        Err(error) => {
+            eprintln!("{}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    println!("# {}", rom.get_title());
+    print!("{}", disassemble(&rom, platform));
+    ExitCode::SUCCESS
+}
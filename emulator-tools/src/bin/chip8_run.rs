@@ -0,0 +1,310 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use emulator_core::chip8_vm::error::VmError;
+use emulator_core::chip8_vm::instruction::{decode, Instruction};
+use emulator_core::chip8_vm::quirks::{Platform, Quirks};
+use emulator_core::chip8_vm::timing::Timing;
+use emulator_core::database::{RomDatabase, RomOverrides};
+use emulator_core::debugger::Debugger;
+use emulator_core::gdb::GdbServer;
+use emulator_core::trace::{TraceFormat, Tracer};
+use emulator_core::Emulator;
+
+const USAGE: &str = "usage: chip8-run <rom> [options]
+
+  --platform NAME        chip8, chip48, schip or xochip (default from the ROM database, else chip8)
+  --frames N             frames to run (default 600)
+  --ips N                instructions per second (default from the ROM database, else 1200)
+  --vip-timing           run frames by COSMAC VIP cycle budget instead of --ips
+  --database PATH        programs.json of the CHIP-8 database, instead of the bundled one
+  --no-database          don't configure known ROMs from the database
+  --seed N               seed for the random number generator (default 0)
+  --until-pc ADDR        stop as soon as PC reaches ADDR, checked after every instruction
+  --until-halt           stop at the first frame that ends on exit or a jump to itself
+  --press FRAME:KEY[:N]  hold KEY (hex) for N frames (default 1) from FRAME, repeatable
+  --png PATH             write the final framebuffer as a PNG
+  --gdb PORT             instead of running, wait for gdb on localhost:PORT and let it drive
+  --trace PATH           write every instruction run to PATH, - for stdout (needs --no-hash)
+  --trace-binary         write the trace in the binary format instead of text
+  --trace-pc START:END   only trace instructions with PC from START to END
+  --no-hash              don't print the hash
+
+Prints the SHA-1 of the final framebuffer and RAM. Exits with 2 if the VM faults.";
+
+const DEFAULT_FRAMES: u64 = 600;
+const FAULT_EXIT_CODE: u8 = 2;
+
+struct KeyPress {
+    key: u8,
+    start: u64,
+    frames: u64,
+}
+
+struct Options {
+    rom: PathBuf,
+    platform: Option<Platform>,
+    frames: u64,
+    instructions_per_second: Option<u32>,
+    database: Option<PathBuf>,
+    no_database: bool,
+    timing: Timing,
+    seed: u64,
+    until_pc: Option<u16>,
+    until_halt: bool,
+    presses: Vec<KeyPress>,
+    png: Option<PathBuf>,
+    gdb: Option<u16>,
+    trace: Option<PathBuf>,
+    trace_format: TraceFormat,
+    trace_pc: Option<(u16, u16)>,
+    no_hash: bool,
+}
+
+fn parse_number<T: TryFrom<u64>>(text: &str) -> Result<T, String> {
+    let value = match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    value
+        .ok()
+        .and_then(|value| T::try_from(value).ok())
+        .ok_or_else(|| format!("invalid number '{}'", text))
+}
+
+fn parse_press(text: &str) -> Result<KeyPress, String> {
+    let parts: Vec<&str> = text.split(':').collect();
+    if parts.len() < 2 || parts.len() > 3 {
+        return Err(format!("invalid key press '{}', expected FRAME:KEY[:N]", text));
+    }
+    let key = u8::from_str_radix(parts[1], 16)
+        .ok()
+        .filter(|key| *key < 16)
+        .ok_or_else(|| format!("invalid key '{}'", parts[1]))?;
+    Ok(KeyPress {
+        key,
+        start: parse_number(parts[0])?,
+        frames: parts.get(2).map_or(Ok(1), |n| parse_number(n))?,
+    })
+}
+
+fn parse_pc_range(text: &str) -> Result<(u16, u16), String> {
+    let (start, end) = text
+        .split_once(':')
+        .ok_or_else(|| format!("invalid PC range '{}', expected START:END", text))?;
+    Ok((parse_number(start)?, parse_number(end)?))
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        rom: PathBuf::new(),
+        platform: None,
+        frames: DEFAULT_FRAMES,
+        instructions_per_second: None,
+        database: None,
+        no_database: false,
+        timing: Timing::Instructions,
+        seed: 0,
+        until_pc: None,
+        until_halt: false,
+        presses: Vec::new(),
+        png: None,
+        gdb: None,
+        trace: None,
+        trace_format: TraceFormat::Text,
+        trace_pc: None,
+        no_hash: false,
+    };
+    let mut rom = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--platform" => options.platform = Some(value()?.parse()?),
+            "--frames" => options.frames = parse_number(&value()?)?,
+            "--ips" => options.instructions_per_second = Some(parse_number(&value()?)?),
+            "--database" => options.database = Some(PathBuf::from(value()?)),
+            "--no-database" => options.no_database = true,
+            "--vip-timing" => options.timing = Timing::CosmacVip,
+            "--seed" => options.seed = parse_number(&value()?)?,
+            "--until-pc" => options.until_pc = Some(parse_number(&value()?)?),
+            "--until-halt" => options.until_halt = true,
+            "--press" => options.presses.push(parse_press(&value()?)?),
+            "--png" => options.png = Some(PathBuf::from(value()?)),
+            "--gdb" => options.gdb = Some(parse_number(&value()?)?),
+            "--trace" => options.trace = Some(PathBuf::from(value()?)),
+            "--trace-binary" => options.trace_format = TraceFormat::Binary,
+            "--trace-pc" => options.trace_pc = Some(parse_pc_range(&value()?)?),
+            "--no-hash" => options.no_hash = true,
+            _ if arg.starts_with("--") => return Err(format!("unknown option '{}'", arg)),
+            _ => rom = Some(PathBuf::from(arg)),
+        }
+    }
+
+    options.rom = rom.ok_or_else(|| USAGE.to_string())?;
+    // scripts read the hash from stdout, it can't end up at the bottom of a trace
+    if options.trace.as_deref() == Some(Path::new("-")) && !options.no_hash {
+        return Err("--trace - writes to stdout, where the hash goes too; add --no-hash".to_string());
+    }
+    Ok(options)
+}
+
+/// Whether the VM is parked on `exit` or a `jump` to itself, as test ROMs do when done.
+fn is_halted(emulator: &Emulator) -> bool {
+    let pc = emulator.chip8_vm.get_pc() as usize;
+    let ram = emulator.chip8_vm.get_ram();
+    let Some(bytes) = ram.get(pc..pc + 2) else {
+        return false;
+    };
+    match decode(u16::from_be_bytes([bytes[0], bytes[1]])) {
+        Instruction::Exit => true,
+        Instruction::Jump { nnn } => nnn as usize == pc,
+        _ => false,
+    }
+}
+
+/// Runs one frame an instruction at a time, returning early with `true` once PC
+/// reaches `until_pc`, which may well happen in the middle of the frame.
+fn run_frame_until_pc(emulator: &mut Emulator, until_pc: Option<u16>) -> Result<bool, VmError> {
+    let frame = emulator.get_frame_count();
+    while emulator.get_frame_count() == frame {
+        emulator.step()?;
+        if until_pc == Some(emulator.chip8_vm.get_pc()) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn write_png(emulator: &mut Emulator, path: &Path) -> Result<(), String> {
+    let (width, height) = emulator.get_resolution();
+    let file = File::create(path).map_err(|error| error.to_string())?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|error| error.to_string())?;
+    writer
+        .write_image_data(&emulator.get_color_bufer())
+        .map_err(|error| error.to_string())
+}
+
+fn main() -> ExitCode {
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut emulator = Emulator::new(Quirks::from_platform(Platform::Chip8));
+    emulator.set_platform(Platform::Chip8);
+    if options.no_database {
+        emulator.set_rom_database(None);
+    } else if let Some(path) = &options.database {
+        match RomDatabase::from_path(path) {
+            Ok(database) => emulator.set_rom_database(Some(database)),
+            Err(error) => {
+                eprintln!("{}: {}", path.display(), error);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    emulator.set_overrides(RomOverrides {
+        platform: options.platform,
+        instructions_per_second: options.instructions_per_second,
+        ..RomOverrides::default()
+    });
+    emulator.set_seed(options.seed);
+    emulator.set_timing(options.timing);
+    if let Err(error) = emulator.load_rom(options.rom.clone()) {
+        eprintln!("{}: {}", options.rom.display(), error);
+        return ExitCode::FAILURE;
+    }
+
+    if let Some(path) = &options.trace {
+        let tracer = if path.as_os_str() == "-" {
+            Ok(Tracer::stdout(options.trace_format))
+        } else {
+            Tracer::create(path, options.trace_format)
+        };
+        match tracer {
+            Ok(mut tracer) => {
+                if let Some((start, end)) = options.trace_pc {
+                    tracer.set_pc_range(start..=end);
+                }
+                emulator.start_trace(tracer);
+            }
+            Err(error) => {
+                eprintln!("{}: {}", path.display(), error);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let mut fault = None;
+    if let Some(port) = options.gdb {
+        let result = GdbServer::bind(port).and_then(|server| {
+            eprintln!("waiting for gdb on {}", server.local_addr()?);
+            server.serve(&mut emulator, &mut Debugger::new())
+        });
+        if let Err(error) = result {
+            eprintln!("gdb: {}", error);
+            return ExitCode::FAILURE;
+        }
+        fault = emulator.get_fault();
+    } else {
+        for frame in 0..options.frames {
+            for key in 0..16 {
+                let held = options.presses.iter().any(|press| {
+                    let end = press.start.saturating_add(press.frames);
+                    press.key == key && (press.start..end).contains(&frame)
+                });
+                emulator.handle_input(key, held);
+            }
+            match run_frame_until_pc(&mut emulator, options.until_pc) {
+                Ok(true) => break,
+                Ok(false) => {}
+                Err(error) => {
+                    fault = Some(error);
+                    break;
+                }
+            }
+            if options.until_halt && is_halted(&emulator) {
+                break;
+            }
+        }
+    }
+
+    if let Err(error) = emulator.stop_trace() {
+        eprintln!("trace: {}", error);
+        return ExitCode::FAILURE;
+    }
+
+    if let Some(path) = &options.png {
+        if let Err(error) = write_png(&mut emulator, path) {
+            eprintln!("{}: {}", path.display(), error);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if !options.no_hash {
+        let hash: String = emulator
+            .get_state_hash()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        println!("{}", hash);
+    }
+
+    match fault {
+        Some(error) => {
+            eprintln!("VM fault: {}", error);
+            ExitCode::from(FAULT_EXIT_CODE)
+        }
+        None => ExitCode::SUCCESS,
+    }
+}
@@ -1,4 +1,10 @@
+use emulator_core::chip8_vm::display::{
+    HIRES_SCREEN_HEIGHT, HIRES_SCREEN_WIDTH, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+use emulator_core::chip8_vm::quirks::{Platform, Quirks};
+use emulator_core::database::RomOverrides;
 use emulator_core::{Emulator, MAX_FRAMES_PER_SECOND};
+use sdl2::keyboard::Keycode;
 use sdl2::video::Window;
 
 use crate::utils;
@@ -11,11 +17,16 @@ use sdl2::video::SwapInterval;
 use std::path::PathBuf;
 use std::time::{Duration, Instant};
 use super::audio::AudioDriver;
+use super::debugger::DebuggerUi;
 
 const APP_TITLE: &str = "CHIPI-8 Emulator";
 const WINDOW_WIDTH: u32 = 1024;
 const WINDOW_HEIGHT: u32 = 512;
 const EMULATOR_CANVAS_SIZE: [f32; 2] = [400.0, 200.0];
+/// Held down to play the game backwards, one frame per frame.
+const REWIND_KEY: Keycode = Keycode::Backspace;
+/// Longest stretch of emulated time run at once, so a stalled window doesn't fast-forward.
+const MAX_CATCH_UP: Duration = Duration::from_millis(100);
 
 pub struct GuiApp {
     emulator: Emulator,
@@ -25,6 +36,9 @@ pub struct GuiApp {
     is_running: bool,
     event_pump: sdl2::EventPump,
     app_start_time: Instant,
+    rom_error: Option<String>,
+    rewinding: bool,
+    debugger: DebuggerUi,
 }
 
 impl GuiApp {
@@ -43,7 +57,7 @@ impl GuiApp {
             .unwrap();
 
         let event_pump = sdl_context.event_pump().unwrap();
-        let emulator = Emulator::new();
+        let emulator = Emulator::new(Quirks::default());
         GuiApp {
             emulator: emulator,
             audio_device: AudioDriver::new(&sdl_context.audio().unwrap()),
@@ -52,6 +66,9 @@ impl GuiApp {
             event_pump,
             is_running: true,
             app_start_time: Instant::now(),
+            rom_error: None,
+            rewinding: false,
+            debugger: DebuggerUi::new(),
         }
     }
 
@@ -69,16 +86,55 @@ impl GuiApp {
             ui.menu_button("File", |ui| {
                 if ui.button("Load room").clicked() {
                     if let Some(path) = Self::get_game_path() {
-                        self.emulator.load_rom(path);
+                        self.rom_error = self.emulator.load_rom(path).err().map(|e| e.to_string());
                     }
                     ui.close_menu();
                 }
             });
+            ui.menu_button("Platform", |ui| {
+                // known ROMs get their platform from the database again
+                if ui.button("Auto").clicked() {
+                    self.emulator.set_overrides(RomOverrides {
+                        platform: None,
+                        ..self.emulator.get_overrides()
+                    });
+                    ui.close_menu();
+                }
+                for (label, platform) in [
+                    ("CHIP-8", Platform::Chip8),
+                    ("CHIP-48", Platform::Chip48),
+                    ("SUPER-CHIP", Platform::SuperChip),
+                    ("XO-CHIP", Platform::XoChip),
+                ] {
+                    if ui.button(label).clicked() {
+                        self.emulator.set_platform(platform);
+                        self.emulator.set_overrides(RomOverrides {
+                            platform: Some(platform),
+                            ..self.emulator.get_overrides()
+                        });
+                        ui.close_menu();
+                    }
+                }
+            });
+            ui.menu_button("Debug", |ui| self.debugger.menu(ui));
             egui::Frame::canvas(ui.style()).show(ui, |ui| {
                 ui.image(emulator_texture_id, EMULATOR_CANVAS_SIZE);
                 ui.label(self.emulator.current_room.get_title());
+                if let Some(info) = self.emulator.get_rom_info() {
+                    ui.label(&info.title);
+                    for (action, key) in &info.keys {
+                        ui.label(format!("{}: {:X}", action, key));
+                    }
+                }
+                if let Some(error) = &self.rom_error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+                if let Some(fault) = self.emulator.get_fault() {
+                    ui.colored_label(egui::Color32::RED, format!("VM halted: {}", fault));
+                }
             });
         });
+        self.debugger.show(&self.egui_ctx, &mut self.emulator);
 
         let FullOutput {
             platform_output,
@@ -113,6 +169,14 @@ impl GuiApp {
             if event.is_some() {
                 match event {
                     Some(Event::Quit { .. }) => self.is_running = false,
+                    Some(Event::KeyDown {
+                        keycode: Some(REWIND_KEY),
+                        ..
+                    }) => self.rewinding = true,
+                    Some(Event::KeyUp {
+                        keycode: Some(REWIND_KEY),
+                        ..
+                    }) => self.rewinding = false,
                     Some(Event::KeyDown {
                         keycode: Some(key), ..
                     }) => {
@@ -138,6 +202,14 @@ impl GuiApp {
         for event in events {
             match event {
                 Event::Quit { .. } => self.is_running = false,
+                Event::KeyDown {
+                    keycode: Some(REWIND_KEY),
+                    ..
+                } => self.rewinding = true,
+                Event::KeyUp {
+                    keycode: Some(REWIND_KEY),
+                    ..
+                } => self.rewinding = false,
                 Event::KeyDown {
                     keycode: Some(key), ..
                 } => {
@@ -164,15 +236,39 @@ impl GuiApp {
         let shader_ver: ShaderVersion = ShaderVersion::Adaptive;
         let (mut painter, mut egui_state) =
             egui_backend::with_sdl2(&self.window, shader_ver, DpiScaling::Custom(2.0));
-        let emulator_texture_id =
-            painter.new_user_texture_rgba8((64, 32), self.emulator.get_color_bufer(), false);
+        // one texture per resolution, SUPER-CHIP programs can switch at any time
+        let lores_texture_id = painter.new_user_texture_rgba8(
+            (SCREEN_WIDTH, SCREEN_HEIGHT),
+            vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4],
+            false,
+        );
+        let hires_texture_id = painter.new_user_texture_rgba8(
+            (HIRES_SCREEN_WIDTH, HIRES_SCREEN_HEIGHT),
+            vec![0; HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT * 4],
+            false,
+        );
 
+        let mut last_update = Instant::now();
         while self.is_running {
+            let now = Instant::now();
+            let elapsed = (now - last_update).min(MAX_CATCH_UP);
+            last_update = now;
             self.window
                 .subsystem()
                 .gl_set_swap_interval(SwapInterval::Immediate)
                 .unwrap();
-            self.emulator.emulate_cycles(20);
+            if self.rewinding {
+                self.emulator.rewind(1);
+            } else {
+                // a fault halts the VM, render_ui shows it until another ROM is loaded
+                let _ = self.debugger.run(&mut self.emulator, elapsed);
+            }
+
+            let emulator_texture_id = if self.emulator.get_resolution() == (SCREEN_WIDTH, SCREEN_HEIGHT) {
+                lores_texture_id
+            } else {
+                hires_texture_id
+            };
 
             // create a canvas to draw on
             if self.emulator.is_draw_flag_set() {
@@ -182,6 +278,7 @@ impl GuiApp {
                 );
             }
 
+            self.audio_device.set_pattern(self.emulator.get_audio_pattern());
             if self.emulator.is_sound_flag_set(){
                 self.audio_device.play();
             }else {
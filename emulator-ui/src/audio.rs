@@ -6,6 +6,10 @@ pub struct SquareWave {
     phase_inc: f32,
     phase: f32,
     volume: f32,
+    sample_rate: f32,
+    // XO-CHIP programs replace the square wave with a 128-bit pattern
+    pattern: Option<[u8; 16]>,
+    pattern_phase_inc: f32,
 }
 
 impl AudioCallback for SquareWave {
@@ -13,12 +17,25 @@ impl AudioCallback for SquareWave {
 
     fn callback(&mut self, out: &mut [f32]) {
         for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 {
-                self.volume
-            } else {
-                -self.volume
-            };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
+            match self.pattern {
+                Some(pattern) => {
+                    let bit = (self.phase * 128.0) as usize % 128;
+                    *x = if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                        self.volume
+                    } else {
+                        -self.volume
+                    };
+                    self.phase = (self.phase + self.pattern_phase_inc) % 1.0;
+                }
+                None => {
+                    *x = if self.phase <= 0.5 {
+                        self.volume
+                    } else {
+                        -self.volume
+                    };
+                    self.phase = (self.phase + self.phase_inc) % 1.0;
+                }
+            }
         }
     }
 }
@@ -42,6 +59,9 @@ impl AudioDriver {
                 phase_inc: 255.0 / spec.freq as f32,
                 phase: 0.0,
                 volume: 0.25,
+                sample_rate: spec.freq as f32,
+                pattern: None,
+                pattern_phase_inc: 0.0,
             }
         }).unwrap();
 
@@ -58,4 +78,16 @@ impl AudioDriver {
     pub fn pause(&self) {
         self.device.pause();
     }
+
+    /// Plays `pattern` at `rate` bits per second instead of the square wave.
+    pub fn set_pattern(&mut self, pattern: Option<([u8; 16], f32)>) {
+        let mut wave = self.device.lock();
+        match pattern {
+            Some((pattern, rate)) => {
+                wave.pattern_phase_inc = rate / 128.0 / wave.sample_rate;
+                wave.pattern = Some(pattern);
+            }
+            None => wave.pattern = None,
+        }
+    }
 }
\ No newline at end of file
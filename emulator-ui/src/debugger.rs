@@ -0,0 +1,256 @@
+use std::time::Duration;
+
+use egui_sdl2_gl::egui;
+use egui::{Color32, RichText};
+use emulator_core::chip8_vm::error::VmError;
+use emulator_core::chip8_vm::instruction::decode;
+use emulator_core::debugger::{Debugger, StopReason};
+use emulator_core::Emulator;
+use super::memory_editor::MemoryEditor;
+use super::sprite_viewer::SpriteViewer;
+
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+const BREAKPOINT_COLOR: Color32 = Color32::from_rgb(220, 50, 50);
+
+/// The debugger windows and the run control they share.
+pub struct DebuggerUi {
+    debugger: Debugger,
+    pub show_registers: bool,
+    pub show_call_stack: bool,
+    pub show_disassembly: bool,
+    memory: MemoryEditor,
+    sprites: SpriteViewer,
+    paused: bool,
+    last_stop: Option<StopReason>,
+    /// Time not yet run while going frame by frame for breakpoints.
+    frame_time: Duration,
+    follow_pc: bool,
+    /// The PC the disassembly was last scrolled to.
+    centered_pc: Option<u16>,
+}
+
+impl Default for DebuggerUi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DebuggerUi {
+    pub fn new() -> Self {
+        DebuggerUi {
+            debugger: Debugger::new(),
+            show_registers: false,
+            show_call_stack: false,
+            show_disassembly: false,
+            memory: MemoryEditor::new(),
+            sprites: SpriteViewer::new(),
+            paused: false,
+            last_stop: None,
+            frame_time: Duration::ZERO,
+            follow_pc: true,
+            centered_pc: None,
+        }
+    }
+
+    /// Runs `elapsed` worth of emulation unless paused. With breakpoints set it
+    /// goes a frame at a time through the debugger, pausing on a hit.
+    pub fn run(&mut self, emulator: &mut Emulator, elapsed: Duration) -> Result<(), VmError> {
+        if self.paused || emulator.current_room.size() == 0 {
+            return Ok(());
+        }
+        if self.debugger.breakpoints().next().is_none() {
+            return emulator.run_for(elapsed);
+        }
+
+        self.frame_time += elapsed;
+        while self.frame_time >= FRAME_DURATION {
+            self.frame_time -= FRAME_DURATION;
+            let frame = emulator.get_frame_count() + 1;
+            match self.debugger.run_until_frame(emulator, frame) {
+                StopReason::Frame { .. } => {}
+                reason => {
+                    self.stop(reason);
+                    break;
+                }
+            }
+        }
+        match emulator.get_fault() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    fn stop(&mut self, reason: StopReason) {
+        self.paused = true;
+        self.last_stop = Some(reason);
+        self.frame_time = Duration::ZERO;
+    }
+
+    /// Entries for the menu bar.
+    pub fn menu(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.show_registers, "Registers");
+        ui.checkbox(&mut self.show_call_stack, "Call stack");
+        ui.checkbox(&mut self.show_disassembly, "Disassembly");
+        ui.checkbox(&mut self.memory.open, "Memory");
+        ui.checkbox(&mut self.sprites.open, "Sprites");
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, emulator: &mut Emulator) {
+        let paused = self.paused;
+        let mut show_registers = self.show_registers;
+        egui::Window::new("Registers")
+            .open(&mut show_registers)
+            .resizable(false)
+            .show(ctx, |ui| Self::registers(ui, emulator, paused));
+        self.show_registers = show_registers;
+
+        let mut show_call_stack = self.show_call_stack;
+        egui::Window::new("Call stack")
+            .open(&mut show_call_stack)
+            .resizable(false)
+            .show(ctx, |ui| Self::call_stack(ui, emulator));
+        self.show_call_stack = show_call_stack;
+
+        let mut show_disassembly = self.show_disassembly;
+        egui::Window::new("Disassembly")
+            .open(&mut show_disassembly)
+            .default_height(320.0)
+            .show(ctx, |ui| {
+                self.controls(ui, emulator);
+                ui.separator();
+                self.disassembly(ui, emulator);
+            });
+        self.show_disassembly = show_disassembly;
+
+        self.memory.show(ctx, emulator, paused);
+        self.sprites.show(ctx, emulator);
+    }
+
+    /// V0-VF and I can be edited while paused.
+    fn registers(ui: &mut egui::Ui, emulator: &mut Emulator, editable: bool) {
+        let vm = &mut emulator.chip8_vm;
+        let v = vm.get_v();
+        egui::Grid::new("registers").striped(true).show(ui, |ui| {
+            for (row, values) in v.chunks(4).enumerate() {
+                for (column, &value) in values.iter().enumerate() {
+                    let x = row * 4 + column;
+                    if editable {
+                        let mut value = value;
+                        let drag = egui::DragValue::new(&mut value)
+                            .hexadecimal(2, false, true)
+                            .prefix(format!("V{:X} ", x));
+                        if ui.add(drag).changed() {
+                            vm.set_v(x, value);
+                        }
+                    } else {
+                        ui.label(RichText::new(format!("V{:X} {:02X}", x, value)).monospace());
+                    }
+                }
+                ui.end_row();
+            }
+            if editable {
+                let mut i = vm.get_i();
+                let drag = egui::DragValue::new(&mut i).hexadecimal(3, false, true).prefix("I  ");
+                if ui.add(drag).changed() {
+                    vm.set_i(i);
+                }
+            } else {
+                ui.label(RichText::new(format!("I  {:03X}", vm.get_i())).monospace());
+            }
+            ui.label(RichText::new(format!("PC {:03X}", vm.get_pc())).monospace());
+            ui.label(RichText::new(format!("SP {:X}", vm.get_call_stack().len())).monospace());
+            ui.end_row();
+            ui.label(RichText::new(format!("DT {:02X}", vm.timers.get_delay_timer())).monospace());
+            ui.label(RichText::new(format!("ST {:02X}", vm.timers.get_sound_timer())).monospace());
+            ui.end_row();
+        });
+    }
+
+    fn call_stack(ui: &mut egui::Ui, emulator: &Emulator) {
+        let calls = emulator.chip8_vm.get_call_stack();
+        if calls.is_empty() {
+            ui.label("No subroutine calls");
+        }
+        // innermost first, each the call that is waiting to return
+        for ret in calls.iter().rev() {
+            let call = ret.wrapping_sub(2);
+            ui.label(RichText::new(format!("{:03X}  returns to {:03X}", call, ret)).monospace());
+        }
+    }
+
+    fn controls(&mut self, ui: &mut egui::Ui, emulator: &mut Emulator) {
+        ui.horizontal(|ui| {
+            if self.paused {
+                if ui.button("Continue").clicked() {
+                    self.paused = false;
+                    self.last_stop = None;
+                }
+            } else if ui.button("Pause").clicked() {
+                self.paused = true;
+            }
+            ui.add_enabled_ui(self.paused && emulator.current_room.size() > 0, |ui| {
+                if ui.button("Step").clicked() {
+                    let reason = self.debugger.step(emulator);
+                    self.stop(reason);
+                }
+                if ui.button("Step over").clicked() {
+                    let reason = self.debugger.step_over(emulator);
+                    self.stop(reason);
+                }
+                if ui.button("Step out").clicked() {
+                    let reason = self.debugger.step_out(emulator);
+                    self.stop(reason);
+                }
+            });
+            ui.checkbox(&mut self.follow_pc, "Follow PC");
+        });
+        if let Some(reason) = self.last_stop {
+            ui.label(format!("Stopped: {}", reason));
+        }
+    }
+
+    /// Every word of memory from the PC's alignment, centered on the PC when it moves.
+    /// Clicking a line toggles its breakpoint.
+    fn disassembly(&mut self, ui: &mut egui::Ui, emulator: &Emulator) {
+        let vm = &emulator.chip8_vm;
+        let ram = vm.get_ram();
+        let pc = vm.get_pc();
+        let alignment = (pc & 1) as usize;
+        let rows = (ram.len() - alignment) / 2;
+
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        let mut scroll_area = egui::ScrollArea::vertical().auto_shrink([false; 2]);
+        if self.follow_pc && self.centered_pc != Some(pc) {
+            self.centered_pc = Some(pc);
+            let spacing = ui.spacing().item_spacing.y;
+            let offset = (pc as usize / 2) as f32 * (row_height + spacing);
+            scroll_area = scroll_area.vertical_scroll_offset((offset - ui.available_height() / 2.0).max(0.0));
+        }
+
+        scroll_area.show_rows(ui, row_height, rows, |ui, rows| {
+            for row in rows {
+                let address = (alignment + row * 2) as u16;
+                let opcode = u16::from_be_bytes([ram[address as usize], ram[address as usize + 1]]);
+                let breakpoint = self.debugger.breakpoints().any(|at| at == address);
+                let text = format!(
+                    "{} {:03X}  {:04X}  {}",
+                    if breakpoint { "●" } else { " " },
+                    address,
+                    opcode,
+                    decode(opcode)
+                );
+                let mut text = RichText::new(text).monospace();
+                if breakpoint {
+                    text = text.color(BREAKPOINT_COLOR);
+                }
+                if ui.selectable_label(address == pc, text).clicked() {
+                    if breakpoint {
+                        self.debugger.remove_breakpoint(address);
+                    } else {
+                        self.debugger.add_breakpoint(address);
+                    }
+                }
+            }
+        });
+    }
+}
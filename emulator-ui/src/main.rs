@@ -1,5 +1,8 @@
 mod app;
 mod audio;
+mod debugger;
+mod memory_editor;
+mod sprite_viewer;
 mod utils;
 
 fn main() {
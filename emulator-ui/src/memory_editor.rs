@@ -0,0 +1,198 @@
+use egui_sdl2_gl::egui;
+use egui::{Color32, RichText};
+use emulator_core::chip8_vm::vm::{FONT_AREA_SIZE, PROGRAM_START_ADDRESS};
+use emulator_core::Emulator;
+
+const BYTES_PER_ROW: usize = 16;
+const CHANGED_COLOR: Color32 = Color32::from_rgb(255, 200, 60);
+const FONT_COLOR: Color32 = Color32::from_rgb(120, 170, 255);
+const PROGRAM_COLOR: Color32 = Color32::from_rgb(140, 220, 140);
+const I_BACKGROUND: Color32 = Color32::from_rgb(110, 60, 140);
+
+/// A hex view over the whole RAM, editable while the emulator is paused.
+pub struct MemoryEditor {
+    pub open: bool,
+    /// RAM as it was when the current frame started, and the frame it belongs to.
+    frame_start: Vec<u8>,
+    frame: u64,
+    /// RAM as it was when the previous frame started, what changes are shown against.
+    previous_frame: Vec<u8>,
+    go_to: String,
+    scroll_to: Option<usize>,
+    /// The byte being edited and the text typed so far.
+    editing: Option<(usize, String)>,
+}
+
+impl Default for MemoryEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryEditor {
+    pub fn new() -> Self {
+        MemoryEditor {
+            open: false,
+            frame_start: Vec::new(),
+            frame: 0,
+            previous_frame: Vec::new(),
+            go_to: String::new(),
+            scroll_to: None,
+            editing: None,
+        }
+    }
+
+    /// Takes a snapshot whenever a new frame has been run, so the bytes that
+    /// changed during the last one can be highlighted.
+    fn track_changes(&mut self, emulator: &Emulator) {
+        let ram = emulator.chip8_vm.get_ram();
+        let frame = emulator.get_frame_count();
+        if self.frame_start.len() != ram.len() || frame < self.frame {
+            // a new ROM or platform, or a rewind: nothing to compare against
+            self.frame_start = ram.to_vec();
+            self.previous_frame = ram.to_vec();
+        } else if frame != self.frame {
+            self.previous_frame = std::mem::replace(&mut self.frame_start, ram.to_vec());
+        }
+        self.frame = frame;
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, emulator: &mut Emulator, editable: bool) {
+        self.track_changes(emulator);
+        if !editable {
+            self.editing = None;
+        }
+
+        let mut open = self.open;
+        egui::Window::new("Memory")
+            .open(&mut open)
+            .default_height(320.0)
+            .show(ctx, |ui| {
+                self.toolbar(ui, emulator);
+                ui.separator();
+                self.rows(ui, emulator, editable);
+            });
+        self.open = open;
+    }
+
+    fn toolbar(&mut self, ui: &mut egui::Ui, emulator: &Emulator) {
+        ui.horizontal(|ui| {
+            ui.label("Go to");
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.go_to)
+                    .char_limit(4)
+                    .desired_width(40.0)
+                    .font(egui::TextStyle::Monospace),
+            );
+            let entered = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if ui.button("Go").clicked() || entered {
+                if let Ok(address) = usize::from_str_radix(self.go_to.trim(), 16) {
+                    self.scroll_to = Some(address.min(emulator.chip8_vm.get_ram().len() - 1));
+                }
+            }
+            if ui.button("Go to I").clicked() {
+                self.scroll_to = Some(emulator.chip8_vm.get_i() as usize);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("font").color(FONT_COLOR));
+            ui.label(RichText::new("program").color(PROGRAM_COLOR));
+            ui.label(RichText::new("I").background_color(I_BACKGROUND));
+            ui.label(RichText::new("changed").color(CHANGED_COLOR));
+        });
+    }
+
+    fn rows(&mut self, ui: &mut egui::Ui, emulator: &mut Emulator, editable: bool) {
+        let i = emulator.chip8_vm.get_i() as usize;
+        let program = PROGRAM_START_ADDRESS..PROGRAM_START_ADDRESS + emulator.current_room.size();
+        let rows = emulator.chip8_vm.get_ram().len() / BYTES_PER_ROW;
+
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        let mut scroll_area = egui::ScrollArea::vertical().auto_shrink([false; 2]);
+        if let Some(address) = self.scroll_to.take() {
+            let spacing = ui.spacing().item_spacing.y;
+            let offset = (address / BYTES_PER_ROW) as f32 * (row_height + spacing);
+            scroll_area = scroll_area.vertical_scroll_offset(offset);
+        }
+
+        scroll_area.show_rows(ui, row_height, rows, |ui, rows| {
+            for row in rows {
+                ui.horizontal(|ui| {
+                    ui.spacing_mut().item_spacing.x = 4.0;
+                    let start = row * BYTES_PER_ROW;
+                    ui.label(RichText::new(format!("{:04X}", start)).monospace().weak());
+                    for address in start..start + BYTES_PER_ROW {
+                        self.cell(ui, emulator, address, editable, |text| {
+                            let mut text = text;
+                            if address < FONT_AREA_SIZE {
+                                text = text.color(FONT_COLOR);
+                            } else if program.contains(&address) {
+                                text = text.color(PROGRAM_COLOR);
+                            }
+                            if address == i {
+                                text = text.background_color(I_BACKGROUND);
+                            }
+                            text
+                        });
+                    }
+                    let ram = &emulator.chip8_vm.get_ram()[start..start + BYTES_PER_ROW];
+                    let ascii: String = ram
+                        .iter()
+                        .map(|&byte| if byte.is_ascii_graphic() { byte as char } else { '.' })
+                        .collect();
+                    ui.label(RichText::new(ascii).monospace().weak());
+                });
+            }
+        });
+    }
+
+    /// One byte: a label, or a text field once clicked while paused.
+    fn cell(
+        &mut self,
+        ui: &mut egui::Ui,
+        emulator: &mut Emulator,
+        address: usize,
+        editable: bool,
+        style: impl Fn(RichText) -> RichText,
+    ) {
+        let ram = emulator.chip8_vm.get_ram_mut();
+        if let Some((editing, text)) = &mut self.editing {
+            if *editing == address {
+                let response = ui.add(
+                    egui::TextEdit::singleline(text)
+                        .char_limit(2)
+                        .desired_width(char_width(ui) * 2.0)
+                        .font(egui::TextStyle::Monospace),
+                );
+                if response.lost_focus() {
+                    if !ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                        if let Ok(value) = u8::from_str_radix(text.trim(), 16) {
+                            ram[address] = value;
+                        }
+                    }
+                    self.editing = None;
+                } else if !response.has_focus() {
+                    response.request_focus();
+                }
+                return;
+            }
+        }
+
+        let value = ram[address];
+        let mut text = style(RichText::new(format!("{:02X}", value)).monospace());
+        if self.previous_frame.get(address).is_some_and(|&old| old != value) {
+            text = text.color(CHANGED_COLOR);
+        }
+        let sense = if editable { egui::Sense::click() } else { egui::Sense::hover() };
+        let response = ui.add(egui::Label::new(text).sense(sense));
+        if response.clicked() {
+            self.editing = Some((address, format!("{:02X}", value)));
+        }
+        response.on_hover_text(format!("{:04X}", address));
+    }
+}
+
+fn char_width(ui: &egui::Ui) -> f32 {
+    let font = egui::TextStyle::Monospace.resolve(ui.style());
+    ui.fonts(|fonts| fonts.glyph_width(&font, '0'))
+}
@@ -0,0 +1,202 @@
+use egui_sdl2_gl::egui;
+use egui::{pos2, vec2, Color32, Pos2, Rect, RichText};
+use emulator_core::chip8_vm::vm::{Chip8VM, PROGRAM_START_ADDRESS};
+use emulator_core::Emulator;
+
+/// Size of a sprite pixel on screen.
+const PIXEL_SCALE: f32 = 4.0;
+/// Sprite rows stacked in one column before starting the next.
+const ROWS_PER_COLUMN: usize = 16;
+const COLUMN_GAP: f32 = 6.0;
+/// Painting 64K of XO-CHIP memory at once is of no use to anyone.
+const MAX_LENGTH: usize = 0x1000;
+const MAX_SMALL_SPRITE_HEIGHT: usize = 15;
+const BACKGROUND_COLOR: Color32 = Color32::from_gray(24);
+const PIXEL_COLOR: Color32 = Color32::from_gray(230);
+const I_HIGHLIGHT: Color32 = Color32::from_rgba_premultiplied(60, 30, 70, 60);
+const SPRITE_HIGHLIGHT: Color32 = Color32::from_rgba_premultiplied(70, 55, 10, 60);
+
+/// RAM rendered as sprite rows, one byte per row or two for 16x16 sprites.
+pub struct SpriteViewer {
+    pub open: bool,
+    start: usize,
+    length: usize,
+    wide: bool,
+}
+
+impl Default for SpriteViewer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpriteViewer {
+    pub fn new() -> Self {
+        SpriteViewer {
+            open: false,
+            start: PROGRAM_START_ADDRESS,
+            length: 0x100,
+            wide: false,
+        }
+    }
+
+    fn bytes_per_row(&self) -> usize {
+        if self.wide { 2 } else { 1 }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, emulator: &Emulator) {
+        let mut open = self.open;
+        egui::Window::new("Sprites")
+            .open(&mut open)
+            .default_width(320.0)
+            .show(ctx, |ui| {
+                self.controls(ui, &emulator.chip8_vm);
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false; 2])
+                    .show(ui, |ui| self.region(ui, &emulator.chip8_vm));
+            });
+        self.open = open;
+    }
+
+    fn controls(&mut self, ui: &mut egui::Ui, vm: &Chip8VM) {
+        let last_address = vm.get_ram().len() - 1;
+        ui.horizontal(|ui| {
+            ui.label("Start");
+            ui.add(
+                egui::DragValue::new(&mut self.start)
+                    .hexadecimal(3, false, true)
+                    .clamp_range(0..=last_address),
+            );
+            ui.label("Bytes");
+            ui.add(
+                egui::DragValue::new(&mut self.length)
+                    .hexadecimal(3, false, true)
+                    .clamp_range(1..=MAX_LENGTH),
+            );
+            if ui.button("Go to I").clicked() {
+                self.start = vm.get_i() as usize;
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.wide, false, "8 wide");
+            ui.radio_value(&mut self.wide, true, "16x16");
+        });
+        match vm.get_last_sprite() {
+            Some(sprite) => {
+                ui.horizontal(|ui| {
+                    ui.label(
+                        RichText::new(format!(
+                            "Last DXYN: {}x{} from {:03X} at ({}, {})",
+                            sprite.width, sprite.height, sprite.address, sprite.x, sprite.y
+                        ))
+                        .monospace(),
+                    );
+                    if ui.button("Go to").clicked() {
+                        self.start = sprite.address as usize;
+                        self.wide = sprite.width == 16;
+                    }
+                });
+                let bytes_per_row = sprite.width as usize / 8;
+                let size = vec2(sprite.width as f32, sprite.height as f32) * PIXEL_SCALE * 2.0;
+                let (response, painter) = ui.allocate_painter(size, egui::Sense::hover());
+                for row in 0..sprite.height as usize {
+                    let address = sprite.address as usize + row * bytes_per_row;
+                    let origin = response.rect.min + vec2(0.0, row as f32 * PIXEL_SCALE * 2.0);
+                    paint_row(&painter, origin, vm.get_ram(), address, bytes_per_row, PIXEL_SCALE * 2.0);
+                }
+            }
+            None => {
+                ui.label("No sprite drawn yet");
+            }
+        }
+    }
+
+    /// The region laid out in columns of `ROWS_PER_COLUMN` rows, wrapped to the window width.
+    /// The sprite at I and the last one drawn are tinted.
+    fn region(&self, ui: &mut egui::Ui, vm: &Chip8VM) {
+        let ram = vm.get_ram();
+        let bytes_per_row = self.bytes_per_row();
+        // switching platform can shrink RAM under the start address
+        let start = self.start.min(ram.len() - 1);
+        let end = (start + self.length).min(ram.len());
+        let rows = (end - start).div_ceil(bytes_per_row);
+
+        let column_size = vec2(
+            (8 * bytes_per_row) as f32 * PIXEL_SCALE,
+            ROWS_PER_COLUMN as f32 * PIXEL_SCALE,
+        );
+        let columns = rows.div_ceil(ROWS_PER_COLUMN);
+        let per_line = (((ui.available_width() + COLUMN_GAP) / (column_size.x + COLUMN_GAP)) as usize).max(1);
+        let lines = columns.div_ceil(per_line);
+        let size = vec2(
+            per_line.min(columns) as f32 * (column_size.x + COLUMN_GAP),
+            lines as f32 * (column_size.y + COLUMN_GAP),
+        );
+        let (response, painter) = ui.allocate_painter(size, egui::Sense::hover());
+
+        let row_origin = |row: usize| -> Pos2 {
+            let column = row / ROWS_PER_COLUMN;
+            response.rect.min
+                + vec2(
+                    (column % per_line) as f32 * (column_size.x + COLUMN_GAP),
+                    (column / per_line) as f32 * (column_size.y + COLUMN_GAP)
+                        + (row % ROWS_PER_COLUMN) as f32 * PIXEL_SCALE,
+                )
+        };
+        let row_rect = |row: usize| Rect::from_min_size(row_origin(row), vec2(column_size.x, PIXEL_SCALE));
+
+        let i = vm.get_i() as usize;
+        let i_sprite = i..i + bytes_per_row * vm.get_last_sprite().map_or(
+            if self.wide { ROWS_PER_COLUMN } else { MAX_SMALL_SPRITE_HEIGHT },
+            |sprite| sprite.height as usize,
+        );
+        let last_sprite = vm.get_last_sprite().map_or(0..0, |sprite| {
+            let address = sprite.address as usize;
+            address..address + sprite.width as usize / 8 * sprite.height as usize
+        });
+
+        let mut hovered = None;
+        for row in 0..rows {
+            let address = start + row * bytes_per_row;
+            paint_row(&painter, row_origin(row), ram, address, bytes_per_row, PIXEL_SCALE);
+            let rect = row_rect(row);
+            if i_sprite.contains(&address) {
+                painter.rect_filled(rect, 0.0, I_HIGHLIGHT);
+            }
+            if last_sprite.contains(&address) {
+                painter.rect_filled(rect, 0.0, SPRITE_HIGHLIGHT);
+            }
+            if response.hover_pos().is_some_and(|pos| rect.contains(pos)) {
+                hovered = Some(address);
+            }
+        }
+        if let Some(address) = hovered {
+            let bytes: Vec<String> = ram[address..(address + bytes_per_row).min(ram.len())]
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect();
+            response.on_hover_text_at_pointer(format!("{:03X}: {}", address, bytes.join(" ")));
+        }
+    }
+}
+
+/// One sprite row, 8 or 16 pixels, with its top-left corner at `origin`.
+fn paint_row(painter: &egui::Painter, origin: Pos2, ram: &[u8], address: usize, bytes_per_row: usize, scale: f32) {
+    let width = 8 * bytes_per_row;
+    painter.rect_filled(
+        Rect::from_min_size(origin, vec2(width as f32 * scale, scale)),
+        0.0,
+        BACKGROUND_COLOR,
+    );
+    let mut pixels = (ram.get(address).copied().unwrap_or(0) as u16) << 8;
+    if bytes_per_row == 2 {
+        pixels |= ram.get(address + 1).copied().unwrap_or(0) as u16;
+    }
+    for x in 0..width {
+        if pixels & (0x8000 >> x) != 0 {
+            let min = pos2(origin.x + x as f32 * scale, origin.y);
+            painter.rect_filled(Rect::from_min_size(min, vec2(scale, scale)), 0.0, PIXEL_COLOR);
+        }
+    }
+}
@@ -5,7 +5,7 @@ use rfd::FileDialog;
 
 pub fn open_file_dialog() -> Option<PathBuf> {
     let file_dialog = FileDialog::new();
-    file_dialog.add_filter("CHIP-8 ROMs", &["ch8"]).pick_file()
+    file_dialog.add_filter("CHIP-8 ROMs", &["ch8", "8o"]).pick_file()
 }
 
 pub fn to_chip8_keycode(key: SdlKeycode) -> Chip8Keycode {